@@ -0,0 +1,144 @@
+use super::json::{self, KeyOrder};
+use super::{from_index, into_index, File, Kind};
+use crate::namespace::Namespace;
+use crate::workspace;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use superfusion::prelude::{Error, Index, Pid, Relation};
+
+pub struct Atlas {
+	pid: Pid,
+	data: AtlasFormat,
+	order: KeyOrder,
+}
+
+impl Atlas {
+	pub fn new(path: impl Into<PathBuf>, pid: Pid) -> Result<Self> {
+		let path = path.into();
+		let reader = std::fs::File::open(&path).with_context(|| "Reading atlas file")?;
+		let (data, order) = json::from_reader(reader).with_context(|| "Parsing atlas file")?;
+		let result = Self { pid, data, order };
+		Ok(result)
+	}
+}
+
+impl Atlas {
+	pub fn into_bytes(self) -> Result<Vec<u8>> {
+		json::to_vec(&self.data, &self.order)
+	}
+}
+
+impl File for Atlas {
+	fn relation(&self) -> Vec<Relation> {
+		let pid = self.pid;
+
+		self.data
+			.sources
+			.iter()
+			.filter_map(AtlasSource::texture)
+			.map(|texture| into_index(Kind::Texture, &texture, pid))
+			.map(Relation::new)
+			.collect()
+	}
+	fn data(self) -> Vec<u8> {
+		super::logged(self.into_bytes())
+	}
+	fn modify_relation(mut self, from: &Index, to: &Index) -> Self
+	where
+		Self: Sized,
+	{
+		if !workspace::texture_folder(from.path()) {
+			return self;
+		}
+
+		let (from, to) = match (from_index(from), from_index(to)) {
+			(Ok(from), Ok(to)) => (from, to),
+			_ => return self,
+		};
+
+		for source in &mut self.data.sources {
+			if source.texture().as_ref() == Some(&from) {
+				source.set_texture(&to);
+			}
+		}
+
+		self
+	}
+	fn merge(mut self, other: Self) -> Result<Self, Error> {
+		for source in other.data.sources {
+			if !self.data.sources.contains(&source) {
+				self.data.sources.push(source);
+			}
+		}
+
+		self.data.other.extend(other.data.other);
+		self.order.merge(other.order);
+		Ok(self)
+	}
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct AtlasFormat {
+	#[serde(default)]
+	sources: Vec<AtlasSource>,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+/// A single atlas source, kept as-is since every `type` has different fields.
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct AtlasSource(Map<String, Value>);
+
+impl AtlasSource {
+	/// Texture added by `single` source
+	fn texture(&self) -> Option<Namespace> {
+		if self.0.get("type")?.as_str()? != "single" {
+			return None;
+		}
+
+		let resource = self.0.get("resource")?.as_str()?;
+		Namespace::try_from(resource).ok()
+	}
+
+	fn set_texture(&mut self, texture: &Namespace) {
+		let resource = Value::String(texture.to_string());
+		self.0.insert("resource".to_string(), resource);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	fn atlas(pid: usize, value: Value) -> Atlas {
+		Atlas {
+			pid: Pid::new(pid),
+			data: serde_json::from_value(value).unwrap(),
+			order: KeyOrder::default(),
+		}
+	}
+
+	#[test]
+	fn merge_atlas_sources() {
+		let directory = json!({ "type": "directory", "source": "item", "prefix": "item/" });
+		let a = atlas(0, json!({ "sources": [directory] }));
+		let b = atlas(
+			1,
+			json!({ "sources": [directory, { "type": "single", "resource": "boomber:item/sword" }] }),
+		);
+
+		let from = Index::new(Pid::new(1), "assets/boomber/textures/item/sword.png");
+		let to = Index::new(Pid::new(1), "assets/boomber/textures/item/sword_1.png");
+		let b = b.modify_relation(&from, &to);
+
+		let result = a.merge(b).unwrap();
+		assert_eq!(
+			serde_json::to_value(&result.data).unwrap(),
+			json!({ "sources": [directory, { "type": "single", "resource": "boomber:item/sword_1" }] })
+		);
+	}
+}
@@ -1,31 +1,46 @@
+use super::json::{self, KeyOrder};
+use super::model::Reference;
 use super::{from_index, into_index, File, Kind};
 use crate::namespace::Namespace;
+use crate::report::{self, Entry};
 use anyhow::{Result, Context};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::collections::HashMap;
-use std::path::Path;
-use superfusion::prelude::{Index, Pid, Relation};
+use indexmap::IndexMap;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use superfusion::prelude::{Error, Index, Pid, Relation};
+use log::*;
 
 pub struct BlockState {
 	pid: Pid,
-	data: BlockstateFormat,
+	path: PathBuf,
+	data: BlockstateFile,
+	order: KeyOrder,
 }
 
 impl BlockState {
-	pub fn new(path: impl AsRef<Path>, pid: Pid) -> Result<Self> {
-		let reader = std::fs::File::open(path)
+	pub fn new(path: impl Into<PathBuf>, pid: Pid) -> Result<Self> {
+		let path = path.into();
+		let reader = std::fs::File::open(&path)
 			.with_context(|| "Reading blockstate file")?;
-		let data = serde_json::from_reader(reader)
+		let (data, order) = json::from_reader(reader)
 			.with_context(|| "Parsing blockstate file")?;
-		let result = Self { data, pid };
+		let result = Self { data, path, pid, order };
 		Ok(result)
 	}
 }
 
+impl BlockState {
+	pub fn into_bytes(self) -> Result<Vec<u8>> {
+		json::to_vec(&self.data, &self.order)
+	}
+}
+
 impl File for BlockState {
 	fn relation(&self) -> Vec<Relation> {
 		self.data
+			.format
 			.relation()
 			.iter()
 			.map(|namespace| into_index(Kind::Model, namespace, self.pid))
@@ -33,17 +48,64 @@ impl File for BlockState {
 			.collect()
 	}
 	fn data(self) -> Vec<u8> {
-		serde_json::to_vec(&self.data).unwrap_or_default()
+		super::logged(self.into_bytes())
 	}
 
 	fn modify_relation(mut self, from: &Index, to: &Index) -> Self
 	where
 		Self: Sized,
 	{
-		let data = self.data.modify(from, to);
-		self.data = data;
+		let data = self.data.format.modify(from, to);
+		self.data.format = data;
 		self
 	}
+
+	fn merge(mut self, other: Self) -> Result<Self, Error>
+	where
+		Self: Sized,
+	{
+		let file = &self.path;
+		self.order.merge(other.order);
+		self.data.other.extend(other.data.other);
+		self.data.format = match (self.data.format, other.data.format) {
+			(BlockstateFormat::Variant(mut a), BlockstateFormat::Variant(b)) => {
+				a.merge(*b, file);
+				BlockstateFormat::Variant(a)
+			}
+			(BlockstateFormat::Multipart(mut a), BlockstateFormat::Multipart(b)) => {
+				a.merge(*b);
+				BlockstateFormat::Multipart(a)
+			}
+			(_, data) => {
+				format_conflict(file);
+				data
+			}
+		};
+
+		Ok(self)
+	}
+}
+
+fn variant_conflict(file: &Path, key: &str) {
+	warn!(
+		"[{file}] Variant '{key}' is defined differently by multiple packs, the later one is used",
+		file = file.display(),
+		key = key,
+	);
+	report::record(Entry::BlockStateVariant {
+		path: report::asset_path(file),
+		variant: key.to_string(),
+	});
+}
+
+fn format_conflict(file: &Path) {
+	warn!(
+		"[{file}] Cannot merge 'variants' with 'multipart' blockstate, the later one is used",
+		file = file.display(),
+	);
+	report::record(Entry::BlockStateFormat {
+		path: report::asset_path(file),
+	});
 }
 
 macro_rules! get_or {
@@ -55,6 +117,15 @@ macro_rules! get_or {
 	};
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+struct BlockstateFile {
+	#[serde(flatten)]
+	format: BlockstateFormat,
+	/// Top-level fields this struct doesn't know about
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 enum BlockstateFormat {
 	#[serde(rename = "variants")]
@@ -77,8 +148,8 @@ impl BlockstateFormat {
 		};
 		namespaces
 			.into_iter()
-			.filter(|v| *v == &from)
-			.for_each(|v| *v = to.clone());
+			.filter(|v| **v == from)
+			.for_each(|v| v.set(to.clone()));
 
 		self
 	}
@@ -89,19 +160,55 @@ impl BlockstateFormat {
 			Self::Multipart(multipart) => multipart.models(),
 		};
 
-		models.iter().map(|f| &f.model).collect()
+		models.iter().map(|f| &f.model.namespace).collect()
 	}
 }
 
 #[derive(Debug, Deserialize, Serialize)]
-struct VariantFormat(HashMap<String, ModelFormat>);
+struct VariantFormat(IndexMap<String, ModelFormat>);
 
 impl VariantFormat {
+	/// Union variants from both blockstates, `other` win when the same variant is defined differently.
+	///
+	/// Variant keys are compared by their properties so `a=1,b=2` and `b=2,a=1` are the same variant.
+	/// Packs usually ship the whole variants file with the block's default model for the states they don't use,
+	/// so a custom model is kept over the default one and only two custom models are a conflict.
+	fn merge(&mut self, other: Self, file: &Path) {
+		let default = default_model(file);
+
+		for (key, model) in other.0 {
+			let existing = self
+				.0
+				.keys()
+				.position(|existing| same_variant(existing, &key));
+
+			match existing.and_then(|index| self.0.get_index_mut(index)) {
+				Some((_, previous)) if *previous == model => {}
+				Some((_, previous)) => {
+					let is_default = |model: &ModelFormat| {
+						default.as_ref().is_some_and(|default| model.is(default))
+					};
+					match (is_default(previous), is_default(&model)) {
+						(false, true) => {}
+						(true, false) => *previous = model,
+						_ => {
+							variant_conflict(file, &key);
+							*previous = model;
+						}
+					}
+				}
+				None => {
+					self.0.insert(key, model);
+				}
+			}
+		}
+	}
+
 	fn models(&self) -> Vec<&Model> {
 		self.0.values().flat_map(ModelFormat::models).collect()
 	}
 
-	fn namespace(&mut self) -> Vec<&mut Namespace> {
+	fn namespace(&mut self) -> Vec<&mut Reference> {
 		self.0
 			.values_mut()
 			.flat_map(ModelFormat::namespace)
@@ -109,35 +216,70 @@ impl VariantFormat {
 	}
 }
 
+/// Multipart cases are applied in order, so this has to stay as a list.
 #[derive(Debug, Deserialize, Serialize)]
-struct MultipartFormat(HashMap<String, CaseFormat>);
+struct MultipartFormat(Vec<CaseFormat>);
 
 impl MultipartFormat {
+	/// Append cases from `other` that don't already exist in this blockstate
+	fn merge(&mut self, other: Self) {
+		for case in other.0 {
+			if !self.0.contains(&case) {
+				self.0.push(case);
+			}
+		}
+	}
+
 	fn models(&self) -> Vec<&Model> {
 		self.0
-			.values()
+			.iter()
 			.map(|case| &case.apply)
 			.flat_map(ModelFormat::models)
 			.collect()
 	}
 
-	fn namespace(&mut self) -> Vec<&mut Namespace> {
+	fn namespace(&mut self) -> Vec<&mut Reference> {
 		self.0
-			.values_mut()
+			.iter_mut()
 			.map(|case| &mut case.apply)
 			.flat_map(ModelFormat::namespace)
 			.collect()
 	}
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
 struct CaseFormat {
 	#[serde(skip_serializing_if = "Option::is_none")]
-	when: Option<Value>,
+	when: Option<Condition>,
 	apply: ModelFormat,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Condition of a multipart case.
+///
+/// Block state values are kept as-is since they can be written as `"true"`, `true` or `"north|south"`.
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+enum Condition {
+	Or(OrCondition),
+	And(AndCondition),
+	State(BTreeMap<String, Value>),
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct OrCondition {
+	#[serde(rename = "OR")]
+	cases: Vec<Condition>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct AndCondition {
+	#[serde(rename = "AND")]
+	cases: Vec<Condition>,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
 #[serde(untagged)]
 enum ModelFormat {
 	Single(Model),
@@ -145,6 +287,11 @@ enum ModelFormat {
 }
 
 impl ModelFormat {
+	/// Whether every model of this variant is `model`
+	fn is(&self, model: &Namespace) -> bool {
+		self.models().iter().all(|m| m.model == *model)
+	}
+
 	fn models(&self) -> Vec<&Model> {
 		match &self {
 			Self::Single(model) => vec![model],
@@ -152,7 +299,7 @@ impl ModelFormat {
 		}
 	}
 
-	fn namespace(&mut self) -> Vec<&mut Namespace> {
+	fn namespace(&mut self) -> Vec<&mut Reference> {
 		match self {
 			Self::Single(model) => vec![model.namespace()],
 			Self::Multiple(models) => models.iter_mut().map(Model::namespace).collect(),
@@ -160,9 +307,9 @@ impl ModelFormat {
 	}
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
 struct Model {
-	model: Namespace,
+	model: Reference,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	x: Option<i32>,
 	#[serde(skip_serializing_if = "Option::is_none")]
@@ -170,11 +317,174 @@ struct Model {
 	#[serde(skip_serializing_if = "Option::is_none")]
 	uvlock: Option<bool>,
 	#[serde(skip_serializing_if = "Option::is_none")]
-	weight: Option<f32>,
+	weight: Option<u32>,
+	#[serde(flatten)]
+	other: Map<String, Value>,
 }
 
 impl Model {
-	fn namespace(&mut self) -> &mut Namespace {
+	fn namespace(&mut self) -> &mut Reference {
 		&mut self.model
 	}
 }
+
+/// Model that the game use for a block, `assets/minecraft/blockstates/note_block.json` → `minecraft:block/note_block`
+fn default_model(file: &Path) -> Option<Namespace> {
+	let block = file.file_stem()?.to_str()?;
+	let namespace = file.parent()?.parent()?.file_name()?.to_str()?;
+	Some(Namespace::new(namespace, Path::new("block").join(block)))
+}
+
+fn same_variant(a: &str, b: &str) -> bool {
+	properties(a) == properties(b)
+}
+
+fn properties(key: &str) -> Vec<&str> {
+	let mut result: Vec<&str> = key.split(',').filter(|p| !p.is_empty()).collect();
+	result.sort_unstable();
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	fn blockstate(value: Value) -> BlockstateFormat {
+		serde_json::from_value(value).unwrap()
+	}
+
+	#[test]
+	fn parse_multipart() {
+		let value = json!({
+			"multipart": [
+				{ "apply": { "model": "minecraft:block/oak_fence_post" } },
+				{ "when": { "north": "true" }, "apply": { "model": "minecraft:block/oak_fence_side", "uvlock": true } },
+				{
+					"when": { "OR": [{ "north": "side|up" }, { "AND": [{ "east": "up" }, { "west": "none" }] }] },
+					"apply": { "model": "minecraft:block/redstone_dust_side0" }
+				}
+			]
+		});
+		let result = blockstate(value.clone());
+		assert_eq!(result.relation().len(), 3);
+		assert_eq!(serde_json::to_value(&result).unwrap(), value);
+	}
+
+	#[test]
+	fn rename_multipart_model() {
+		let result = blockstate(json!({
+			"multipart": [
+				{ "when": { "north": "true" }, "apply": [{ "model": "boomber:block/fence_side" }] }
+			]
+		}));
+		let from = Namespace::new("boomber", "block/fence_side");
+		let to = Namespace::new("boomber", "block/fence_side_1");
+		let result = result.replace(from, to.clone());
+		assert_eq!(result.relation(), vec![&to]);
+	}
+
+	#[test]
+	fn merge_variants() {
+		let mut a = match blockstate(json!({
+			"variants": {
+				"instrument=banjo,note=0,powered=false": { "model": "boomber:block/custom_a" },
+				"instrument=harp,note=0,powered=false": { "model": "minecraft:block/note_block" }
+			}
+		})) {
+			BlockstateFormat::Variant(variant) => variant,
+			_ => unreachable!(),
+		};
+		let b = match blockstate(json!({
+			"variants": {
+				"instrument=bass,note=0,powered=false": { "model": "korone:block/custom_b" },
+				"powered=false,note=0,instrument=harp": { "model": "minecraft:block/note_block" }
+			}
+		})) {
+			BlockstateFormat::Variant(variant) => variant,
+			_ => unreachable!(),
+		};
+
+		a.merge(*b, Path::new("assets/minecraft/blockstates/note_block.json"));
+		assert_eq!(a.0.len(), 3);
+		assert!(a.0.contains_key("instrument=bass,note=0,powered=false"));
+	}
+
+	#[test]
+	fn merge_full_variants() {
+		let variants = |custom: &str, model: &str| {
+			let mut variants = Map::new();
+			for instrument in &["banjo", "bass", "harp"] {
+				let key = format!("instrument={},note=0,powered=false", instrument);
+				let model = match *instrument == custom {
+					true => model,
+					false => "minecraft:block/note_block",
+				};
+				variants.insert(key, json!({ "model": model }));
+			}
+			match blockstate(json!({ "variants": variants })) {
+				BlockstateFormat::Variant(variant) => variant,
+				_ => unreachable!(),
+			}
+		};
+
+		let mut a = variants("banjo", "boomber:block/custom_a");
+		let b = variants("bass", "korone:block/custom_b");
+		a.merge(*b, Path::new("assets/minecraft/blockstates/note_block.json"));
+
+		let models: Vec<String> = a.models().iter().map(|m| m.model.to_string()).collect();
+		assert_eq!(
+			models,
+			vec![
+				"boomber:block/custom_a",
+				"korone:block/custom_b",
+				"minecraft:block/note_block"
+			]
+		);
+		assert!(!report::entries().iter().any(|entry| matches!(
+			entry,
+			Entry::BlockStateVariant { variant, .. } if variant.contains("instrument=bass")
+		)));
+	}
+
+	#[test]
+	fn merge_custom_vanilla_namespace() {
+		let variants = |banjo: &str, harp: &str| {
+			match blockstate(json!({
+				"variants": {
+					"instrument=banjo,note=1,powered=false": { "model": banjo },
+					"instrument=harp,note=1,powered=false": { "model": harp }
+				}
+			})) {
+				BlockstateFormat::Variant(variant) => variant,
+				_ => unreachable!(),
+			}
+		};
+
+		let mut a = variants("minecraft:block/custom/banjo0", "minecraft:block/custom/harp0");
+		let b = variants("minecraft:block/note_block", "minecraft:block/custom/harp1");
+		a.merge(*b, Path::new("assets/minecraft/blockstates/note_block.json"));
+
+		let models: Vec<String> = a.models().iter().map(|m| m.model.to_string()).collect();
+		assert_eq!(
+			models,
+			vec!["minecraft:block/custom/banjo0", "minecraft:block/custom/harp1"]
+		);
+
+		let conflicts: Vec<String> = report::entries()
+			.into_iter()
+			.filter_map(|entry| match entry {
+				Entry::BlockStateVariant { variant, .. } if variant.contains("note=1") => Some(variant),
+				_ => None,
+			})
+			.collect();
+		assert_eq!(conflicts, vec!["instrument=harp,note=1,powered=false"]);
+	}
+
+	#[test]
+	fn round_trip_unknown_fields() {
+		let source = r#"{"variants":{"facing=north":{"model":"boomber:block/machine","y":90,"weight":2,"optifine_extra":true},"facing=south":{"model":"block/furnace"}},"comment":"kept"}"#;
+		let data: BlockstateFile = serde_json::from_str(source).unwrap();
+		assert_eq!(serde_json::to_string(&data).unwrap(), source);
+	}
+}
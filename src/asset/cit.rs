@@ -0,0 +1,351 @@
+use super::properties::{self, Line};
+use super::{normalize, rename_reference, File};
+use crate::report;
+use anyhow::{Context, Result};
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+use superfusion::prelude::{Index, Pid, Relation};
+
+/// OptiFine custom item texture rule (`optifine/cit/**/*.properties`), refer to textures and models.
+pub struct Cit {
+	pid: Pid,
+	/// Path inside the resourcepack, references are resolved from it
+	path: PathBuf,
+	lines: Vec<Line>,
+}
+
+impl Cit {
+	pub fn new(path: impl AsRef<Path>, pid: Pid) -> Result<Self> {
+		let path = path.as_ref();
+		let data = fs::read(path).with_context(|| "Reading CIT properties")?;
+		// OptiFine read properties as ISO-8859-1, every byte is a character of the same value
+		let content: String = data.into_iter().map(char::from).collect();
+		let result = Self {
+			pid,
+			path: report::asset_path(path),
+			lines: parse(&content),
+		};
+		Ok(result)
+	}
+
+	/// What the rule matches, rules with the same matcher in different packs fight over the same items.
+	///
+	/// Every matcher property is part of it, so rules that differ in e.g. `enchantments` or `hand` don't clash.
+	pub fn rule(&self) -> Option<String> {
+		let mut items: Vec<&str> = self
+			.get("items")
+			.or_else(|| self.get("matchItems"))?
+			.split_whitespace()
+			.map(|item| item.trim_start_matches("minecraft:"))
+			.collect();
+		items.sort_unstable();
+		items.dedup();
+
+		let mut rule = vec![
+			format!("type={}", self.get("type").unwrap_or("item")),
+			format!("items={}", items.join(" ")),
+		];
+
+		let mut matchers: Vec<String> = self
+			.entries()
+			.filter(|&(key, _)| matcher(key))
+			.map(|(key, value)| match key {
+				"enchantmentIDs" => format!("enchantments={}", value),
+				_ => format!("{}={}", key, value),
+			})
+			.collect();
+		matchers.sort();
+		rule.append(&mut matchers);
+
+		Some(rule.join(" "))
+	}
+
+	fn get(&self, key: &str) -> Option<&str> {
+		self.entries()
+			.find(|&(name, _)| name == key)
+			.map(|(_, value)| value)
+	}
+
+	fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+		properties::entries(&self.lines).map(|(key, value)| (key.as_str(), value.as_str()))
+	}
+
+	/// Files that the rule refer to along with the line they are on,
+	/// the texture with the same name as the rule is used when no texture or model is given.
+	fn references(&self) -> Vec<(Option<usize>, PathBuf)> {
+		let mut references: Vec<(Option<usize>, PathBuf)> = self
+			.lines
+			.iter()
+			.enumerate()
+			.filter_map(|(n, line)| match line {
+				Line::Entry { key, value } => {
+					let path = self.resolve(value, extension(key)?);
+					Some((Some(n), path))
+				}
+				Line::Text(_) => None,
+			})
+			.collect();
+
+		if self.implicit() {
+			references.push((None, self.path.with_extension("png")));
+		}
+
+		references
+	}
+
+	/// Whether the rule use the texture with the same name as itself
+	fn implicit(&self) -> bool {
+		self.get("texture").is_none() && self.get("model").is_none()
+	}
+
+	/// Path of a texture or model reference.
+	///
+	/// Plain names and `./` are relative to the rule, `~/` is the `optifine` (or `mcpatcher`) folder,
+	/// other paths are relative to `assets/minecraft` unless they have a namespace.
+	fn resolve(&self, value: &str, extension: &str) -> PathBuf {
+		let folder = self.path.parent().unwrap_or_else(|| Path::new(""));
+
+		let path = if let Some(path) = value.strip_prefix("~/") {
+			self.root().join(path)
+		} else if let Some((namespace, path)) = value.split_once(':') {
+			Path::new("assets").join(namespace).join(path)
+		} else if value.starts_with("./") || !value.contains('/') {
+			folder.join(value)
+		} else {
+			Path::new("assets/minecraft").join(value)
+		};
+
+		let path = normalize(&path);
+		if path.extension().is_some_and(|ext| ext == extension) {
+			return path;
+		}
+
+		let mut path = path.into_os_string();
+		path.push(".");
+		path.push(extension);
+		PathBuf::from(path)
+	}
+
+	/// Folder that `~/` refer to
+	fn root(&self) -> PathBuf {
+		let legacy = self.path.components().any(|c| c.as_os_str() == "mcpatcher");
+		match legacy {
+			true => PathBuf::from("assets/minecraft/mcpatcher"),
+			false => PathBuf::from("assets/minecraft/optifine"),
+		}
+	}
+}
+
+impl File for Cit {
+	/// The rule itself is listed as well, so that it knows when its own file is renamed
+	fn relation(&self) -> Vec<Relation> {
+		self.references()
+			.into_iter()
+			.map(|(_, path)| path)
+			.chain(Some(self.path.clone()))
+			.map(|path| Relation::new(Index::new(self.pid, path)))
+			.collect()
+	}
+	fn data(self) -> Vec<u8> {
+		properties::write(&self.lines)
+			.chars()
+			.map(|c| u8::try_from(c).unwrap_or(b'?'))
+			.collect()
+	}
+	fn modify_relation(mut self, from: &Index, to: &Index) -> Self
+	where
+		Self: Sized,
+	{
+		if from.path() == to.path() {
+			return self;
+		}
+
+		// The texture with the same name as the rule stays behind when only the rule is renamed
+		if from.path() == self.path && self.implicit() {
+			let texture = self.path.file_stem().unwrap_or_default();
+			self.lines.push(Line::Entry {
+				key: "texture".to_string(),
+				value: texture.to_string_lossy().to_string(),
+			});
+			return self;
+		}
+
+		for (line, path) in self.references() {
+			if path != from.path() {
+				continue;
+			}
+
+			match line.map(|n| &mut self.lines[n]) {
+				Some(Line::Entry { value, .. }) => {
+					let extension = Path::new(value.as_str()).extension().is_some();
+					*value = rename_reference(value, to.path(), extension);
+				}
+				// The renamed texture no longer has the same name as the rule
+				_ => self.lines.push(Line::Entry {
+					key: "texture".to_string(),
+					value: rename_reference("", to.path(), false),
+				}),
+			}
+		}
+
+		self
+	}
+}
+
+/// Properties that decide which items a rule apply to, apart from `type` and `items`
+fn matcher(key: &str) -> bool {
+	const MATCHERS: [&str; 8] = [
+		"damage",
+		"damageMask",
+		"stackSize",
+		"enchantments",
+		"enchantmentIDs",
+		"enchantmentLevels",
+		"hand",
+		"weight",
+	];
+	MATCHERS.contains(&key) || key.starts_with("nbt.") || key.starts_with("components.")
+}
+
+/// Extension of the file that a key refer to, `texture.bow_pulling_0` and `model.bow_standby` included
+fn extension(key: &str) -> Option<&'static str> {
+	let kind = key.split('.').next()?;
+	match kind {
+		"texture" => Some("png"),
+		"model" => Some("json"),
+		_ => None,
+	}
+}
+
+fn parse(content: &str) -> Vec<Line> {
+	content
+		.lines()
+		.map(|line| {
+			let trimmed = line.trim_start();
+			if trimmed.starts_with('#') || trimmed.starts_with('!') {
+				return Line::Text(line.to_string());
+			}
+
+			match trimmed.split_once(['=', ':']) {
+				Some((key, value)) => Line::Entry {
+					key: key.trim().to_string(),
+					value: value.trim().to_string(),
+				},
+				None => Line::Text(line.to_string()),
+			}
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn cit(path: &str, content: &str) -> Cit {
+		Cit {
+			pid: Pid::new(0),
+			path: PathBuf::from(path),
+			lines: parse(content),
+		}
+	}
+
+	fn paths(cit: &Cit) -> Vec<PathBuf> {
+		cit.references().into_iter().map(|(_, path)| path).collect()
+	}
+
+	#[test]
+	fn resolve_cit_references() {
+		let rule = cit(
+			"assets/minecraft/optifine/cit/swords/excalibur.properties",
+			"type=item\nitems=diamond_sword\ntexture=excalibur_blade\nmodel=~/cit/models/sword\ntexture.bow_pulling_0=boomber:textures/item/bow.png\n",
+		);
+		assert_eq!(
+			paths(&rule),
+			vec![
+				PathBuf::from("assets/minecraft/optifine/cit/swords/excalibur_blade.png"),
+				PathBuf::from("assets/minecraft/optifine/cit/models/sword.json"),
+				PathBuf::from("assets/boomber/textures/item/bow.png"),
+			]
+		);
+
+		let legacy = cit(
+			"assets/minecraft/mcpatcher/cit/sword.properties",
+			"# Sword\nmatchItems=minecraft:iron_sword\n",
+		);
+		assert_eq!(
+			paths(&legacy),
+			vec![PathBuf::from("assets/minecraft/mcpatcher/cit/sword.png")]
+		);
+		assert_eq!(legacy.rule().as_deref(), Some("type=item items=iron_sword"));
+	}
+
+	#[test]
+	fn rule_matchers() {
+		let sharp = cit(
+			"assets/minecraft/optifine/cit/sharp.properties",
+			"items=diamond_sword\nenchantmentIDs=sharpness\nhand=main\ntexture=sharp\n",
+		);
+		let smite = cit(
+			"assets/minecraft/optifine/cit/smite.properties",
+			"items=minecraft:diamond_sword\nhand=main\nenchantments=smite\n",
+		);
+
+		assert_eq!(
+			sharp.rule().as_deref(),
+			Some("type=item items=diamond_sword enchantments=sharpness hand=main")
+		);
+		assert_ne!(sharp.rule(), smite.rule());
+	}
+
+	#[test]
+	fn rename_cit_texture() {
+		let rule = cit(
+			"assets/minecraft/optifine/cit/sword.properties",
+			"items=diamond_sword\nnbt.display.Name=Excalibur\n",
+		);
+
+		let from = Index::new(Pid::new(0), "assets/minecraft/optifine/cit/sword.png");
+		let to = Index::new(Pid::new(0), "assets/minecraft/optifine/cit/sword_1.png");
+		let rule = rule.modify_relation(&from, &to);
+
+		assert_eq!(
+			properties::write(&rule.lines),
+			"items=diamond_sword\nnbt.display.Name=Excalibur\ntexture=sword_1\n"
+		);
+		assert_eq!(paths(&rule), vec![PathBuf::from(to.path())]);
+	}
+
+	#[test]
+	fn keep_latin1_bytes() {
+		let data = b"items=diamond_sword\nnbt.display.Name=Caf\xe9\n";
+		let content: String = data.iter().map(|&b| char::from(b)).collect();
+		let rule = cit("assets/minecraft/optifine/cit/latin.properties", &content);
+
+		assert_eq!(rule.get("nbt.display.Name"), Some("Café"));
+		assert_eq!(rule.data(), data.to_vec());
+	}
+
+	#[test]
+	fn rename_cit_rule() {
+		let rule = cit(
+			"assets/minecraft/optifine/cit/sword.properties",
+			"items=diamond_sword\n",
+		);
+
+		let from = Index::new(
+			Pid::new(0),
+			"assets/minecraft/optifine/cit/sword.properties",
+		);
+		let to = Index::new(
+			Pid::new(0),
+			"assets/minecraft/optifine/cit/sword_0.properties",
+		);
+		let rule = rule.modify_relation(&from, &to);
+
+		assert_eq!(
+			properties::write(&rule.lines),
+			"items=diamond_sword\ntexture=sword\n"
+		);
+	}
+}
@@ -0,0 +1,524 @@
+use super::json::{self, KeyOrder};
+use super::{from_index, into_index, File, Kind};
+use crate::config;
+use crate::namespace::Namespace;
+use crate::report::{self, Entry};
+use crate::workspace;
+use anyhow::{Context, Result};
+use lazy_static::lazy_static;
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use superfusion::prelude::{Error, Index, Pid, Relation};
+
+pub struct Font {
+	pid: Pid,
+	path: PathBuf,
+	data: FontFormat,
+	order: KeyOrder,
+	/// Pack and provider that currently define each bitmap glyph
+	glyphs: BTreeMap<char, (Pid, Arc<Provider>)>,
+}
+
+impl Font {
+	pub fn new(path: impl Into<PathBuf>, pid: Pid) -> Result<Self> {
+		let path = path.into();
+		let reader = std::fs::File::open(&path).with_context(|| "Reading font file")?;
+		let (mut data, order): (FontFormat, _) =
+			json::from_reader(reader).with_context(|| "Parsing font file")?;
+		data.remap(&GlyphRemap::font(&path, pid));
+		let glyphs = data.owners(pid);
+		let result = Self {
+			pid,
+			path,
+			data,
+			order,
+			glyphs,
+		};
+		Ok(result)
+	}
+}
+
+impl Font {
+	pub fn into_bytes(self) -> Result<Vec<u8>> {
+		json::to_vec(&self.data, &self.order)
+	}
+}
+
+impl File for Font {
+	fn relation(&self) -> Vec<Relation> {
+		let pid = self.pid;
+		let mut result = vec![];
+
+		for provider in &self.data.providers {
+			if let Some(texture) = provider.texture() {
+				result.push(into_index(Kind::Texture, &texture, pid));
+			}
+
+			if let Some(font) = provider.reference() {
+				result.push(into_index(Kind::Font, &font, pid));
+			}
+		}
+
+		result.into_iter().map(Relation::new).collect()
+	}
+	fn data(self) -> Vec<u8> {
+		super::logged(self.into_bytes())
+	}
+	fn modify_relation(mut self, from: &Index, to: &Index) -> Self
+	where
+		Self: Sized,
+	{
+		if !workspace::texture_folder(from.path()) {
+			return self;
+		}
+
+		let (from, to) = match (from_index(from), from_index(to)) {
+			(Ok(from), Ok(to)) => (from, to),
+			_ => return self,
+		};
+
+		for provider in &mut self.data.providers {
+			if provider.texture().as_ref() == Some(&from) {
+				provider.set_texture(&to);
+			}
+		}
+
+		// Renamed texture make the provider different from the other packs' one
+		self.glyphs = self.data.owners(self.pid);
+		self
+	}
+	fn merge(mut self, other: Self) -> Result<Self, Error> {
+		let config = config::get();
+
+		for (glyph, (pid, provider)) in other.glyphs {
+			let owner = (pid, provider.clone());
+			if let Some((previous, previous_provider)) = self.glyphs.insert(glyph, owner) {
+				// Identical providers are deduplicated by the merge, so they do not conflict
+				if previous != pid && previous_provider != provider {
+					glyph_conflict(
+						&self.path,
+						glyph,
+						&config.pack_name(previous),
+						&config.pack_name(pid),
+					);
+				}
+			}
+		}
+
+		self.data = self.data.merge(other.data);
+		self.order.merge(other.order);
+		Ok(self)
+	}
+}
+
+impl Font {
+	/// Every bitmap glyph defined by this font and the provider defining it
+	pub fn codepoints(&self) -> impl Iterator<Item = (char, Arc<Provider>)> + '_ {
+		self.glyphs
+			.iter()
+			.map(|(&glyph, (_, provider))| (glyph, provider.clone()))
+	}
+}
+
+/// Font file inside the resourcepack and the pack that the glyphs are moved for
+type RemapKey = (PathBuf, Pid);
+
+lazy_static! {
+	static ref REMAP: RwLock<HashMap<RemapKey, BTreeMap<char, char>>> = RwLock::new(HashMap::new());
+}
+
+/// Unused codepoints in the Private Use Areas, from the lowest one
+fn private_use(used: &BTreeSet<char>) -> impl Iterator<Item = char> + '_ {
+	(0xE000..=0xF8FF)
+		.chain(0xF0000..=0xFFFFD)
+		.filter_map(char::from_u32)
+		.filter(move |glyph| !used.contains(glyph))
+}
+
+/// Whether the glyph is in one of the Private Use Areas, the only codepoints that packs are free to use
+fn private_use_area(glyph: char) -> bool {
+	matches!(glyph as u32, 0xE000..=0xF8FF | 0xF0000..=0xFFFFD | 0x100000..=0x10FFFD)
+}
+
+/// Path of a font file and the glyphs every pack define in it, along with their provider
+pub type FontGlyphs = (PathBuf, Vec<(Pid, BTreeMap<char, Arc<Provider>>)>);
+
+/// Move glyphs that are defined by multiple packs in the same font to unused codepoints.
+///
+/// `fonts` contain the glyphs of every pack for each font file, the first pack to define a glyph keep it.
+/// Only glyphs in the Private Use Areas are moved, other codepoints are real characters that
+/// every text would have to change with. Glyphs of identical providers (e.g. a copy of the vanilla font)
+/// are left alone. `ttf` providers are not detected, that would mean reading the font files themselves.
+/// Fonts and language files that are read afterward use the new codepoints.
+pub fn remap_glyphs(fonts: &[FontGlyphs]) {
+	let config = config::get();
+	let used: BTreeSet<char> = fonts
+		.iter()
+		.flat_map(|(_, packs)| packs)
+		.flat_map(|(_, glyphs)| glyphs.keys().copied())
+		.collect();
+	let mut free = private_use(&used);
+	let mut table: HashMap<RemapKey, BTreeMap<char, char>> = HashMap::new();
+
+	for (path, packs) in fonts {
+		let mut owners: BTreeMap<char, Vec<(Pid, &Arc<Provider>)>> = BTreeMap::new();
+		for (pid, glyphs) in packs {
+			for (&glyph, provider) in glyphs {
+				owners.entry(glyph).or_default().push((*pid, provider));
+			}
+		}
+
+		for (glyph, owners) in owners {
+			if !private_use_area(glyph) {
+				continue;
+			}
+
+			let (_, first) = owners[0];
+			for &(pid, provider) in owners.iter().skip(1) {
+				if provider == first {
+					continue;
+				}
+
+				let remap = table.entry((report::asset_path(path), pid)).or_default();
+				if remap.contains_key(&glyph) {
+					continue;
+				}
+
+				let to = match free.next() {
+					Some(to) => to,
+					None => {
+						warn!("No unused codepoint left to remap {}", codepoint(glyph));
+						return set_remap(table);
+					}
+				};
+
+				info!(
+					"[{file}] Remap glyph {from} of {pack} to {to}",
+					file = path.display(),
+					from = codepoint(glyph),
+					pack = config.pack_name(pid),
+					to = codepoint(to),
+				);
+				report::record(Entry::FontGlyphRemapped {
+					path: report::asset_path(path),
+					pack: config.pack_name(pid),
+					from: codepoint(glyph),
+					to: codepoint(to),
+				});
+				remap.insert(glyph, to);
+			}
+		}
+	}
+
+	set_remap(table);
+}
+
+fn set_remap(table: HashMap<RemapKey, BTreeMap<char, char>>) {
+	if let Ok(mut current) = REMAP.write() {
+		*current = table;
+	}
+}
+
+/// Codepoints of a pack that were moved by [remap_glyphs()](fn.remap_glyphs.html)
+pub struct GlyphRemap(BTreeMap<char, char>);
+
+impl GlyphRemap {
+	/// Glyphs moved in every font of the pack, text doesn't say which font it is displayed with
+	pub fn of(pid: Pid) -> Self {
+		let mut result = BTreeMap::new();
+		if let Ok(table) = REMAP.read() {
+			let mut fonts: Vec<_> = table
+				.iter()
+				.filter(|((_, owner), _)| *owner == pid)
+				.collect();
+			fonts.sort_by_key(|((path, _), _)| path);
+			for (_, remap) in fonts {
+				result.extend(remap);
+			}
+		}
+		Self(result)
+	}
+
+	/// Glyphs moved in a single font file of the pack
+	pub fn font(path: &Path, pid: Pid) -> Self {
+		let key = (report::asset_path(path), pid);
+		let table = REMAP
+			.read()
+			.ok()
+			.and_then(|table| table.get(&key).cloned())
+			.unwrap_or_default();
+		Self(table)
+	}
+
+	pub fn apply(&self, text: &str) -> String {
+		text.chars()
+			.map(|glyph| self.0.get(&glyph).copied().unwrap_or(glyph))
+			.collect()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+}
+
+fn codepoint(glyph: char) -> String {
+	format!("U+{:04X}", glyph as u32)
+}
+
+fn glyph_conflict(file: &Path, glyph: char, previous: &str, pack: &str) {
+	let codepoint = codepoint(glyph);
+	warn!(
+		"[{file}] Glyph {codepoint} is defined by both {previous} and {pack}, {pack} take precedence",
+		file = file.display(),
+		codepoint = codepoint,
+		previous = previous,
+		pack = pack,
+	);
+	report::record(Entry::FontGlyph {
+		path: report::asset_path(file),
+		codepoint,
+		previous: previous.to_string(),
+		pack: pack.to_string(),
+	});
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct FontFormat {
+	#[serde(default)]
+	providers: Vec<Provider>,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+impl FontFormat {
+	/// Minecraft use the first provider that define a glyph, so the newer providers are placed first.
+	fn merge(self, other: Self) -> Self {
+		let mut providers = other.providers;
+		for provider in self.providers {
+			if !providers.contains(&provider) {
+				providers.push(provider);
+			}
+		}
+
+		let mut rest = self.other;
+		rest.extend(other.other);
+
+		Self {
+			providers,
+			other: rest,
+		}
+	}
+
+	/// Provider that define each glyph, Minecraft use the first one when there are more
+	fn owners(&self, pid: Pid) -> BTreeMap<char, (Pid, Arc<Provider>)> {
+		let mut result = BTreeMap::new();
+
+		for provider in &self.providers {
+			let owner = Arc::new(provider.clone());
+			for glyph in provider.glyphs() {
+				result.entry(glyph).or_insert_with(|| (pid, owner.clone()));
+			}
+		}
+
+		result
+	}
+
+	fn remap(&mut self, remap: &GlyphRemap) {
+		if remap.is_empty() {
+			return;
+		}
+
+		for provider in &mut self.providers {
+			provider.remap(remap);
+		}
+	}
+}
+
+/// A single font provider, kept as-is since every `type` has different fields.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Provider(Map<String, Value>);
+
+impl Provider {
+	fn kind(&self) -> Option<&str> {
+		self.0.get("type")?.as_str()
+	}
+
+	fn string(&self, key: &str) -> Option<&str> {
+		self.0.get(key)?.as_str()
+	}
+
+	/// Texture used by `bitmap` provider, the resource location include the `.png` extension
+	fn texture(&self) -> Option<Namespace> {
+		if self.kind() != Some("bitmap") {
+			return None;
+		}
+
+		let file = self.string("file")?;
+		let file = file.strip_suffix(".png").unwrap_or(file);
+		Namespace::try_from(file).ok()
+	}
+
+	fn set_texture(&mut self, texture: &Namespace) {
+		let file = format!("{}.png", texture);
+		self.0.insert("file".to_string(), Value::String(file));
+	}
+
+	/// Font included by `reference` provider
+	fn reference(&self) -> Option<Namespace> {
+		if self.kind() != Some("reference") {
+			return None;
+		}
+
+		Namespace::try_from(self.string("id")?).ok()
+	}
+
+	/// Move the codepoints of `bitmap` provider, a row keep its length so glyphs stay in place on the texture
+	fn remap(&mut self, remap: &GlyphRemap) {
+		if self.kind() != Some("bitmap") {
+			return;
+		}
+
+		let rows = self.0.get_mut("chars").and_then(Value::as_array_mut);
+		for row in rows.into_iter().flatten() {
+			if let Value::String(text) = row {
+				*text = remap.apply(text);
+			}
+		}
+	}
+
+	/// Every codepoint of `bitmap` provider, except `\0` which is used as padding
+	fn glyphs(&self) -> Vec<char> {
+		if self.kind() != Some("bitmap") {
+			return vec![];
+		}
+
+		let rows = self.0.get("chars").and_then(Value::as_array);
+		rows.into_iter()
+			.flatten()
+			.filter_map(Value::as_str)
+			.flat_map(str::chars)
+			.filter(|&glyph| glyph != '\0')
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	fn font(pid: usize, value: Value) -> Font {
+		let data: FontFormat = serde_json::from_value(value).unwrap();
+		let pid = Pid::new(pid);
+		Font {
+			pid,
+			path: PathBuf::from("assets/minecraft/font/default.json"),
+			glyphs: data.owners(pid),
+			data,
+			order: KeyOrder::default(),
+		}
+	}
+
+	#[test]
+	fn merge_font_providers() {
+		let space = json!({ "type": "space", "advances": { " ": 4 } });
+		let a = font(
+			0,
+			json!({ "providers": [
+				{ "type": "bitmap", "file": "boomber:font/icons.png", "ascent": 8, "chars": ["\u{e000}\u{e001}"] },
+				space
+			] }),
+		);
+		let b = font(
+			1,
+			json!({ "providers": [
+				{ "type": "bitmap", "file": "other:font/icons.png", "ascent": 8, "chars": ["\u{e001}\u{0000}"] },
+				space
+			] }),
+		);
+
+		let from = Index::new(Pid::new(0), "assets/boomber/textures/font/icons.png");
+		let to = Index::new(Pid::new(0), "assets/boomber/textures/font/icons_0.png");
+		let a = a.modify_relation(&from, &to);
+
+		let result = a.merge(b).unwrap();
+		assert_eq!(result.glyphs.len(), 2);
+		assert_eq!(result.glyphs[&'\u{e001}'].0, Pid::new(1));
+		assert_eq!(
+			serde_json::to_value(&result.data).unwrap(),
+			json!({ "providers": [
+				{ "type": "bitmap", "file": "other:font/icons.png", "ascent": 8, "chars": ["\u{e001}\u{0000}"] },
+				space,
+				{ "type": "bitmap", "file": "boomber:font/icons_0.png", "ascent": 8, "chars": ["\u{e000}\u{e001}"] }
+			] })
+		);
+	}
+
+	#[test]
+	fn identical_providers_do_not_conflict() {
+		let ascii = json!({ "type": "bitmap", "file": "minecraft:font/ascii.png", "ascent": 7, "chars": ["ab"] });
+		let mut a = font(0, json!({ "providers": [ascii] }));
+		let mut b = font(1, json!({ "providers": [ascii] }));
+		a.path = PathBuf::from("assets/minecraft/font/identical.json");
+		b.path = a.path.clone();
+
+		let result = a.merge(b).unwrap();
+		assert_eq!(result.data.providers.len(), 1);
+		assert!(!report::entries().iter().any(|entry| matches!(
+			entry,
+			Entry::FontGlyph { path, .. } if path.ends_with("identical.json")
+		)));
+	}
+
+	#[test]
+	fn remap_colliding_glyphs() {
+		let shared =
+			json!({ "type": "bitmap", "file": "boomber:font/shared.png", "chars": ["\u{e005}"] });
+		let a = font(
+			7,
+			json!({ "providers": [
+				{ "type": "bitmap", "file": "boomber:font/a.png", "chars": ["\u{e000}\u{e001}"] },
+				{ "type": "bitmap", "file": "boomber:font/a_ascii.png", "chars": ["a"] },
+				shared
+			] }),
+		);
+		let b = font(
+			8,
+			json!({ "providers": [
+				{ "type": "bitmap", "file": "boomber:font/b.png", "chars": ["\u{e001}\u{e003}"] },
+				{ "type": "bitmap", "file": "boomber:font/b_ascii.png", "chars": ["a"] },
+				shared
+			] }),
+		);
+		let fonts = vec![(
+			PathBuf::from("assets/minecraft/font/default.json"),
+			vec![
+				(a.pid, a.codepoints().collect()),
+				(b.pid, b.codepoints().collect()),
+			],
+		)];
+
+		remap_glyphs(&fonts);
+
+		// Only the PUA glyph of different providers is moved
+		assert!(GlyphRemap::of(a.pid).is_empty());
+		assert_eq!(
+			GlyphRemap::of(b.pid).apply("\u{e001}\u{e003}\u{e005}a!"),
+			"\u{e002}\u{e003}\u{e005}a!"
+		);
+
+		// Other fonts of the pack keep their glyphs
+		let default = Path::new("packs/b/assets/minecraft/font/default.json");
+		let icons = Path::new("packs/b/assets/boomber/font/icons.json");
+		assert_eq!(
+			GlyphRemap::font(default, b.pid).apply("\u{e001}"),
+			"\u{e002}"
+		);
+		assert!(GlyphRemap::font(icons, b.pid).is_empty());
+	}
+}
@@ -0,0 +1,141 @@
+use crate::config;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::Read;
+
+/// Key order of JSON objects as they appear in the original file, including nested objects.
+///
+/// Typed formats serialize their fields in declaration order, this is used to put them back where they were.
+/// Every element of an array share the same order, since merging can reorder or add elements.
+#[derive(Debug, Default, Clone)]
+pub struct KeyOrder {
+	keys: Vec<String>,
+	/// Order inside the value of each key
+	fields: HashMap<String, KeyOrder>,
+}
+
+impl KeyOrder {
+	fn new(value: &Value) -> Self {
+		match value {
+			Value::Object(map) => Self {
+				keys: map.keys().cloned().collect(),
+				fields: map
+					.iter()
+					.filter(|(_, value)| value.is_object() || value.is_array())
+					.map(|(key, value)| (key.clone(), Self::new(value)))
+					.collect(),
+			},
+			Value::Array(items) => items.iter().fold(Self::default(), |mut order, item| {
+				order.merge(Self::new(item));
+				order
+			}),
+			_ => Self::default(),
+		}
+	}
+
+	/// Add keys that only `other` know about after the existing ones
+	pub fn merge(&mut self, other: Self) {
+		for key in other.keys {
+			if !self.keys.contains(&key) {
+				self.keys.push(key);
+			}
+		}
+
+		for (key, order) in other.fields {
+			self.fields.entry(key).or_default().merge(order);
+		}
+	}
+
+	/// Rearrange the object's keys to match this order, new keys are placed at the end.
+	fn apply(&self, value: Value) -> Value {
+		let mut map = match value {
+			Value::Object(map) => map,
+			Value::Array(items) => {
+				return Value::Array(items.into_iter().map(|item| self.apply(item)).collect())
+			}
+			value => return value,
+		};
+
+		let mut result = Map::with_capacity(map.len());
+
+		for key in &self.keys {
+			if let Some(value) = map.remove(key) {
+				result.insert(key.clone(), value);
+			}
+		}
+
+		result.extend(map);
+
+		for (key, value) in result.iter_mut() {
+			if let Some(order) = self.fields.get(key) {
+				*value = order.apply(value.take());
+			}
+		}
+
+		Value::Object(result)
+	}
+}
+
+pub fn from_reader<T, R>(reader: R) -> Result<(T, KeyOrder)>
+where
+	T: DeserializeOwned,
+	R: Read,
+{
+	let value: Value = serde_json::from_reader(reader)?;
+	let order = KeyOrder::new(&value);
+	let data = serde_json::from_value(value)?;
+	Ok((data, order))
+}
+
+/// Serialize in the original key order, minified unless pretty-printing is enabled in the config
+pub fn to_vec<T: Serialize>(data: &T, order: &KeyOrder) -> Result<Vec<u8>> {
+	write(data, order, config::get().pretty)
+}
+
+fn write<T: Serialize>(data: &T, order: &KeyOrder, pretty: bool) -> Result<Vec<u8>> {
+	let value = serde_json::to_value(data)?;
+	let value = order.apply(value);
+
+	let result = if pretty {
+		serde_json::to_vec_pretty(&value)?
+	} else {
+		serde_json::to_vec(&value)?
+	};
+	Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use indexmap::IndexMap;
+	use serde::Deserialize;
+
+	#[derive(Deserialize, Serialize)]
+	struct Event {
+		sounds: Vec<Value>,
+		subtitle: String,
+	}
+
+	#[test]
+	fn keep_nested_key_order() {
+		let source = r#"{"b":{"subtitle":"boom","sounds":[{"volume":1,"name":"a"}]},"a":{"subtitle":"fizz","sounds":[]}}"#;
+		let (data, order): (IndexMap<String, Event>, _) = from_reader(source.as_bytes()).unwrap();
+
+		let result = write(&data, &order, false).unwrap();
+		assert_eq!(String::from_utf8(result).unwrap(), source);
+	}
+
+	#[test]
+	fn pretty_print() {
+		let (data, order): (Value, _) = from_reader(r#"{"b":1,"a":[]}"#.as_bytes()).unwrap();
+
+		let result = write(&data, &order, true).unwrap();
+		assert_eq!(
+			String::from_utf8(result).unwrap(),
+			"{\n  \"b\": 1,\n  \"a\": []\n}"
+		);
+	}
+}
@@ -1,29 +1,195 @@
+use super::font::GlyphRemap;
+use super::json::{self, KeyOrder};
+use super::properties::{self, Line};
 use super::File;
+use crate::config;
+use crate::namespace::Namespace;
+use crate::report::{self, Entry};
+use crate::workspace::{self, Workspace};
+use crate::Error as Err;
 use anyhow::{Context, Result};
+use indexmap::IndexMap;
 use log::*;
+use serde::Serialize;
+use std::collections::HashMap;
 use std::fs;
-use std::{
-	collections::HashMap,
-	path::{Path, PathBuf},
-};
-use superfusion::prelude::{Error, Index, Relation};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use superfusion::prelude::{Error, Index, Pid, Relation, Workspace as _};
+
+/// Format of a language file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LangFormat {
+	/// `en_us.json` used since 1.13 (pack_format 4)
+	Json,
+	/// `en_US.lang` with `key=value` lines used before 1.13
+	Legacy,
+}
+
+impl LangFormat {
+	pub fn from_pack_format(pack_format: u32) -> Self {
+		if pack_format < 4 {
+			Self::Legacy
+		} else {
+			Self::Json
+		}
+	}
+
+	/// Path of the language file once converted to this format
+	pub fn convert(self, path: &Path) -> PathBuf {
+		let stem = path
+			.file_stem()
+			.unwrap_or_default()
+			.to_string_lossy()
+			.to_lowercase();
+
+		match self {
+			Self::Json => path.with_file_name(format!("{}.json", stem)),
+			Self::Legacy => {
+				// Region part of the language code is uppercase: `en_us` → `en_US`
+				let name = match stem.split_once('_') {
+					Some((language, region)) => format!("{}_{}", language, region.to_uppercase()),
+					None => stem,
+				};
+				path.with_file_name(format!("{}.lang", name))
+			}
+		}
+	}
+}
+
+/// What to do when multiple packs define the same language key
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LangPolicy {
+	FirstWins,
+	#[default]
+	LastWins,
+	/// Stop the merge
+	Fail,
+	/// Keep the first value, the later one is moved to a renamed key
+	KeepBoth,
+}
+
+impl FromStr for LangPolicy {
+	type Err = Err;
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		match value {
+			"first-wins" => Ok(Self::FirstWins),
+			"last-wins" => Ok(Self::LastWins),
+			"fail" => Ok(Self::Fail),
+			"keep-both" => Ok(Self::KeepBoth),
+			_ => Err(Err::InvalidLangPolicy(value.to_string())),
+		}
+	}
+}
+
+/// Policy for the language keys in a scope, written as `[scope=]policy`.
+///
+/// Scope ending with `:` is a namespace (`boomber:`), otherwise it is a key prefix (`item.boomber.`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct LangRule {
+	scope: Scope,
+	policy: LangPolicy,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Scope {
+	All,
+	Namespace(String),
+	Prefix(String),
+}
+
+impl LangRule {
+	/// How specific this rule is for the given key, `None` if it does not apply.
+	///
+	/// Key prefixes are more specific than namespaces, and longer prefixes more than shorter ones.
+	pub fn specificity(&self, namespace: &str, key: &str) -> Option<usize> {
+		match &self.scope {
+			Scope::All => Some(0),
+			Scope::Namespace(scope) if scope == namespace => Some(1),
+			Scope::Prefix(prefix) if key.starts_with(prefix.as_str()) => Some(2 + prefix.len()),
+			_ => None,
+		}
+	}
+
+	pub fn policy(&self) -> LangPolicy {
+		self.policy
+	}
+}
+
+impl FromStr for LangRule {
+	type Err = Err;
+	fn from_str(value: &str) -> Result<Self, Self::Err> {
+		let (scope, policy) = match value.rsplit_once('=') {
+			Some((scope, policy)) => match scope.strip_suffix(':') {
+				Some(namespace) => (Scope::Namespace(namespace.to_string()), policy),
+				None => (Scope::Prefix(scope.to_string()), policy),
+			},
+			None => (Scope::All, value),
+		};
+
+		let policy = policy.parse()?;
+		Ok(Self { scope, policy })
+	}
+}
 
 #[derive(Debug)]
 pub struct Lang {
+	pid: Pid,
 	path: PathBuf,
-	data: HashMap<String, String>,
+	format: LangFormat,
+	lines: Vec<Line>,
 }
 
 impl Lang {
-	pub fn new(path: impl Into<PathBuf>) -> Result<Self> {
+	pub fn new(path: impl Into<PathBuf>, pid: Pid) -> Result<Self> {
 		let path = path.into();
-		let reader = fs::File::open(&path)
-			.with_context(|| "Reading language file")?;
-		let data = serde_json::from_reader(reader)
-			.with_context(|| "Parsing language file")?;
-		let result = Self { path, data };
+
+		if workspace::legacy_lang_folder(&path) {
+			let content = fs::read_to_string(&path).with_context(|| "Reading language file")?;
+			let mut lines = parse_legacy(&content);
+			remap_glyphs(&mut lines, pid);
+			let result = Self {
+				pid,
+				path,
+				format: LangFormat::Legacy,
+				lines,
+			};
+			return Ok(result);
+		}
+
+		let reader = fs::File::open(&path).with_context(|| "Reading language file")?;
+		let data: IndexMap<String, String> =
+			serde_json::from_reader(reader).with_context(|| "Parsing language file")?;
+		let mut lines: Vec<Line> = data
+			.into_iter()
+			.map(|(key, value)| Line::Entry { key, value })
+			.collect();
+		remap_glyphs(&mut lines, pid);
+		let result = Self {
+			pid,
+			path,
+			format: LangFormat::Json,
+			lines,
+		};
 		Ok(result)
 	}
+
+	pub fn into_bytes(self) -> Result<Vec<u8>> {
+		let format = config::get().lang_format().unwrap_or(self.format);
+
+		match format {
+			LangFormat::Json => {
+				let data: IndexMap<&String, &String> = self.entries().collect();
+				json::to_vec(&data, &KeyOrder::default())
+			}
+			LangFormat::Legacy => Ok(properties::write(&self.lines).into_bytes()),
+		}
+	}
+
+	fn entries(&self) -> impl Iterator<Item = (&String, &String)> {
+		properties::entries(&self.lines)
+	}
 }
 
 impl File for Lang {
@@ -31,7 +197,7 @@ impl File for Lang {
 		vec![]
 	}
 	fn data(self) -> Vec<u8> {
-		serde_json::to_vec(&self.data).unwrap_or_default()
+		super::logged(self.into_bytes())
 	}
 	fn modify_relation(self, _: &Index, _: &Index) -> Self
 	where
@@ -40,25 +206,189 @@ impl File for Lang {
 		self
 	}
 	fn merge(mut self, other: Self) -> Result<Self, Error> {
-		let file = &self.path;
-		for (key, value) in other.data {
-			if let Some(previous) = self.data.get(&key) {
-				key_conflict(file, &key, &previous, &value);
+		let config = config::get();
+		let namespace = namespace(&self.path);
+		let lines = &mut self.lines;
+		let mut positions: HashMap<String, usize> = lines
+			.iter()
+			.enumerate()
+			.filter_map(|(n, line)| match line {
+				Line::Entry { key, .. } => Some((key.clone(), n)),
+				Line::Text(_) => None,
+			})
+			.collect();
+
+		for line in other.lines {
+			let (key, value) = match line {
+				Line::Entry { key, value } => (key, value),
+				text => {
+					lines.push(text);
+					continue;
+				}
+			};
+
+			let previous = match positions.get(&key).map(|&n| &mut lines[n]) {
+				Some(Line::Entry {
+					value: previous, ..
+				}) => previous,
+				_ => {
+					positions.insert(key.clone(), lines.len());
+					lines.push(Line::Entry { key, value });
+					continue;
+				}
+			};
+
+			if *previous == value {
+				continue;
 			}
 
-			self.data.insert(key, value);
+			let policy = config.lang_policy(&namespace, &key);
+			let renamed = match policy {
+				LangPolicy::KeepBoth => Some(Workspace::formatter(&other.pid, &key)),
+				_ => None,
+			};
+			key_conflict(&self.path, &key, previous, &value, policy, renamed.clone());
+
+			match (policy, renamed) {
+				(LangPolicy::LastWins, _) => *previous = value,
+				(LangPolicy::Fail, _) => {
+					let path = report::asset_path(&self.path);
+					return Err(Error::custom(Err::LangConflict(path, key)));
+				}
+				(LangPolicy::KeepBoth, Some(renamed)) => {
+					positions.insert(renamed.clone(), lines.len());
+					lines.push(Line::Entry {
+						key: renamed,
+						value,
+					});
+				}
+				_ => {}
+			}
 		}
 
 		Ok(self)
 	}
 }
 
-fn key_conflict(file: &Path, key: &str, from: &str, to: &str) {
+/// Use the new codepoints of the pack's remapped font glyphs
+fn remap_glyphs(lines: &mut [Line], pid: Pid) {
+	let remap = GlyphRemap::of(pid);
+	if remap.is_empty() {
+		return;
+	}
+
+	for line in lines {
+		if let Line::Entry { value, .. } = line {
+			*value = remap.apply(value);
+		}
+	}
+}
+
+/// Namespace the language file belongs to
+fn namespace(path: &Path) -> String {
+	Namespace::from_path(report::asset_path(path))
+		.map(|namespace| namespace.header)
+		.unwrap_or_default()
+}
+
+/// Parse `key=value` lines, the value is kept raw so escape sequences are not touched
+fn parse_legacy(content: &str) -> Vec<Line> {
+	let content = content.trim_start_matches('\u{feff}');
+
+	content
+		.lines()
+		.map(|line| match line.split_once('=') {
+			Some((key, value)) if !line.starts_with('#') => Line::Entry {
+				key: key.to_string(),
+				value: value.to_string(),
+			},
+			_ => Line::Text(line.to_string()),
+		})
+		.collect()
+}
+
+fn key_conflict(
+	file: &Path,
+	key: &str,
+	from: &str,
+	to: &str,
+	policy: LangPolicy,
+	renamed: Option<String>,
+) {
 	warn!(
-		"[{file}] Key '{key}' already exists. Replace {from:?} with {to:?}",
+		"[{file}] Key '{key}' already exists with {from:?}, got {to:?} ({policy:?})",
 		file = file.display(),
 		key = key,
 		from = from,
 		to = to,
+		policy = policy,
 	);
+	report::record(Entry::LangKey {
+		path: report::asset_path(file),
+		key: key.to_string(),
+		previous: from.to_string(),
+		value: to.to_string(),
+		policy,
+		renamed,
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn legacy(content: &str) -> Lang {
+		Lang {
+			pid: Pid::new(0),
+			path: PathBuf::from("assets/minecraft/lang/en_US.lang"),
+			format: LangFormat::Legacy,
+			lines: parse_legacy(content),
+		}
+	}
+
+	#[test]
+	fn merge_legacy_lang() {
+		let a = legacy("# Items\nitem.sword.name=Sword\n\nitem.bow.name=Bow=Arrow\n");
+		let b = legacy("item.bow.name=Longbow\n# Blocks\ntile.stone.name=Stone\\nBlock\n");
+
+		let result = a.merge(b).unwrap();
+		assert_eq!(
+			properties::write(&result.lines),
+			"# Items\nitem.sword.name=Sword\n\nitem.bow.name=Longbow\n# Blocks\ntile.stone.name=Stone\\nBlock\n"
+		);
+	}
+
+	#[test]
+	fn convert_lang_path() {
+		let json = Path::new("assets/minecraft/lang/en_us.json");
+		let legacy = Path::new("assets/minecraft/lang/en_US.lang");
+		assert_eq!(LangFormat::Legacy.convert(json), legacy);
+		assert_eq!(LangFormat::Json.convert(legacy), json);
+	}
+
+	#[test]
+	fn select_lang_policy() {
+		let config = config::Config {
+			lang_policies: vec![
+				"first-wins".parse().unwrap(),
+				"boomber:=fail".parse().unwrap(),
+				"item.boomber.=keep-both".parse().unwrap(),
+			],
+			..Default::default()
+		};
+
+		assert_eq!(
+			config.lang_policy("minecraft", "block.stone"),
+			LangPolicy::FirstWins
+		);
+		assert_eq!(
+			config.lang_policy("boomber", "block.stone"),
+			LangPolicy::Fail
+		);
+		assert_eq!(
+			config.lang_policy("boomber", "item.boomber.sword"),
+			LangPolicy::KeepBoth
+		);
+		assert!("boomber:=sometimes".parse::<LangRule>().is_err());
+	}
 }
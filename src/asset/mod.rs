@@ -1,22 +1,40 @@
 use super::workspace;
 use super::Error as Err;
 use anyhow::{Context, Result};
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 use superfusion::prelude::{Error, File, Index, Pid, Relation};
 use log::*;
 
+mod atlas;
 mod blockstate;
+mod cit;
+pub mod font;
+mod json;
 mod lang;
 mod model;
 mod other;
+mod pack_meta;
+mod particle;
+mod properties;
+mod shader;
+mod sounds;
+mod text;
 mod texture;
 
 use crate::namespace::{Kind, Namespace};
+pub use atlas::Atlas;
 pub use blockstate::BlockState;
-pub use lang::Lang;
+pub use cit::Cit;
+pub use font::Font;
+pub use lang::{Lang, LangFormat, LangPolicy, LangRule};
 pub use model::Model;
 pub use other::Other;
-pub use texture::Texture;
+pub use pack_meta::PackMeta;
+pub use particle::Particle;
+pub use shader::{PostEffect, ShaderProgram, ShaderSource};
+pub use sounds::Sounds;
+pub use text::Text;
+pub use texture::{Sidecar, Texture};
 
 pub fn into_index(kind: Kind, namespace: &Namespace, pid: Pid) -> Index {
 	let path = namespace.to_path(kind);
@@ -29,12 +47,53 @@ pub fn from_index(index: &Index) -> Result<Namespace> {
 		.with_context(|| format!("'{}' cannot be converted to namespace", path.display()))
 }
 
+/// Replace the file name of a reference with the renamed file, keeping its namespace and directories
+pub fn rename_reference(reference: &str, to: &Path, extension: bool) -> String {
+	let name = match extension {
+		true => to.file_name(),
+		false => to.file_stem(),
+	};
+	let name = name.unwrap_or_default().to_string_lossy();
+
+	match reference.rfind(['/', ':']) {
+		Some(n) => format!("{}{}", &reference[..=n], name),
+		None => name.to_string(),
+	}
+}
+
+/// Resolve `.` and `..` without touching the filesystem
+pub fn normalize(path: &Path) -> PathBuf {
+	let mut result = PathBuf::new();
+
+	for component in path.components() {
+		match component {
+			Component::CurDir => {}
+			Component::ParentDir => {
+				result.pop();
+			}
+			component => result.push(component),
+		}
+	}
+
+	result
+}
+
 pub enum Asset {
 	Lang(Box<Lang>),
 	Texture(Box<Texture>),
 	Model(Box<Model>),
 	Other(Box<Other>),
 	BlockState(Box<BlockState>),
+	PackMeta(Box<PackMeta>),
+	Sounds(Box<Sounds>),
+	Font(Box<Font>),
+	Atlas(Box<Atlas>),
+	ShaderProgram(Box<ShaderProgram>),
+	ShaderSource(Box<ShaderSource>),
+	PostEffect(Box<PostEffect>),
+	Particle(Box<Particle>),
+	Text(Box<Text>),
+	Cit(Box<Cit>),
 }
 
 impl Asset {
@@ -47,7 +106,7 @@ impl Asset {
 					.with_context(|| format!("Failed to read model file at {}", path.display()))?;
 				Asset::Model(Box::new(data))
 			} else if workspace::lang_folder(path) {
-				let data = Lang::new(path).with_context(|| {
+				let data = Lang::new(path, pid).with_context(|| {
 					format!("Failed to read language file at {}", path.display())
 				})?;
 				Asset::Lang(Box::new(data))
@@ -61,6 +120,53 @@ impl Asset {
 					format!("Failed to read blockstate file at {}", path.display())
 				})?;
 				Asset::BlockState(Box::new(data))
+			} else if workspace::pack_meta(path) {
+				let data = PackMeta::new(path, pid).with_context(|| {
+					format!("Failed to read pack metadata file at {}", path.display())
+				})?;
+				Asset::PackMeta(Box::new(data))
+			} else if workspace::sounds(path) {
+				let data = Sounds::new(path, pid).with_context(|| {
+					format!("Failed to read sounds file at {}", path.display())
+				})?;
+				Asset::Sounds(Box::new(data))
+			} else if workspace::font_folder(path) {
+				let data = Font::new(path, pid)
+					.with_context(|| format!("Failed to read font file at {}", path.display()))?;
+				Asset::Font(Box::new(data))
+			} else if workspace::atlas_folder(path) {
+				let data = Atlas::new(path, pid)
+					.with_context(|| format!("Failed to read atlas file at {}", path.display()))?;
+				Asset::Atlas(Box::new(data))
+			} else if workspace::shader_program(path) {
+				let data = ShaderProgram::new(path, pid).with_context(|| {
+					format!("Failed to read shader program at {}", path.display())
+				})?;
+				Asset::ShaderProgram(Box::new(data))
+			} else if workspace::shader_source(path) {
+				let data = ShaderSource::new(path, pid).with_context(|| {
+					format!("Failed to read shader source at {}", path.display())
+				})?;
+				Asset::ShaderSource(Box::new(data))
+			} else if workspace::post_effect(path) {
+				let data = PostEffect::new(path, pid).with_context(|| {
+					format!("Failed to read post effect at {}", path.display())
+				})?;
+				Asset::PostEffect(Box::new(data))
+			} else if workspace::particle_folder(path) {
+				let data = Particle::new(path, pid).with_context(|| {
+					format!("Failed to read particle file at {}", path.display())
+				})?;
+				Asset::Particle(Box::new(data))
+			} else if workspace::text_file(path) {
+				let data = Text::new(path)
+					.with_context(|| format!("Failed to read text file at {}", path.display()))?;
+				Asset::Text(Box::new(data))
+			} else if workspace::cit_properties(path) {
+				let data = Cit::new(path, pid).with_context(|| {
+					format!("Failed to read CIT properties at {}", path.display())
+				})?;
+				Asset::Cit(Box::new(data))
 			} else {
 				let data = Other::new(path)
 					.with_context(|| format!("Failed to read file at {}", path.display()))?;
@@ -69,6 +175,35 @@ impl Asset {
 		};
 		Ok(asset)
 	}
+
+	/// Fallible version of `File::data()`, used when exporting so that serialization errors are not lost
+	pub fn into_bytes(self) -> Result<Vec<u8>> {
+		use Asset::*;
+
+		match self {
+			Lang(lang) => lang.into_bytes(),
+			Texture(texture) => Ok(texture.data()),
+			Model(model) => model.into_bytes(),
+			BlockState(blockstate) => blockstate.into_bytes(),
+			PackMeta(pack_meta) => pack_meta.into_bytes(),
+			Sounds(sounds) => sounds.into_bytes(),
+			Font(font) => font.into_bytes(),
+			Atlas(atlas) => atlas.into_bytes(),
+			ShaderProgram(program) => program.into_bytes(),
+			ShaderSource(source) => Ok(source.data()),
+			PostEffect(effect) => effect.into_bytes(),
+			Particle(particle) => particle.into_bytes(),
+			Text(text) => Ok(text.data()),
+			Cit(cit) => Ok(cit.data()),
+			Other(other) => Ok(other.data()),
+		}
+	}
+}
+
+/// `File::data()` cannot fail, log the error instead of silently writing nothing
+fn logged(data: Result<Vec<u8>>) -> Vec<u8> {
+	data.map_err(|err| error!("Failed to serialize asset: {:?}", err))
+		.unwrap_or_default()
 }
 
 impl File for Asset {
@@ -80,19 +215,21 @@ impl File for Asset {
 			Texture(texture) => texture.relation(),
 			Model(model) => model.relation(),
 			BlockState(blockstate) => blockstate.relation(),
+			PackMeta(pack_meta) => pack_meta.relation(),
+			Sounds(sounds) => sounds.relation(),
+			Font(font) => font.relation(),
+			Atlas(atlas) => atlas.relation(),
+			ShaderProgram(program) => program.relation(),
+			ShaderSource(source) => source.relation(),
+			PostEffect(effect) => effect.relation(),
+			Particle(particle) => particle.relation(),
+			Text(text) => text.relation(),
+			Cit(cit) => cit.relation(),
 			Other(other) => other.relation(),
 		}
 	}
 	fn data(self) -> Vec<u8> {
-		use Asset::*;
-
-		match self {
-			Lang(lang) => lang.data(),
-			Texture(texture) => texture.data(),
-			Model(model) => model.data(),
-			BlockState(blockstate) => blockstate.data(),
-			Other(other) => other.data(),
-		}
+		logged(self.into_bytes())
 	}
 	fn modify_relation(self, from: &Index, to: &Index) -> Self
 	where
@@ -105,6 +242,16 @@ impl File for Asset {
 			Texture(texture) => Texture(Box::new(texture.modify_relation(from, to))),
 			Model(model) => Model(Box::new(model.modify_relation(from, to))),
 			BlockState(blockstate) => BlockState(Box::new(blockstate.modify_relation(from, to))),
+			PackMeta(pack_meta) => PackMeta(Box::new(pack_meta.modify_relation(from, to))),
+			Sounds(sounds) => Sounds(Box::new(sounds.modify_relation(from, to))),
+			Font(font) => Font(Box::new(font.modify_relation(from, to))),
+			Atlas(atlas) => Atlas(Box::new(atlas.modify_relation(from, to))),
+			ShaderProgram(program) => ShaderProgram(Box::new(program.modify_relation(from, to))),
+			ShaderSource(source) => ShaderSource(Box::new(source.modify_relation(from, to))),
+			PostEffect(effect) => PostEffect(Box::new(effect.modify_relation(from, to))),
+			Particle(particle) => Particle(Box::new(particle.modify_relation(from, to))),
+			Text(text) => Text(Box::new(text.modify_relation(from, to))),
+			Cit(cit) => Cit(Box::new(cit.modify_relation(from, to))),
 			Other(other) => Other(Box::new(other.modify_relation(from, to))),
 		}
 	}
@@ -120,6 +267,16 @@ impl File for Asset {
 			(Texture(a), Texture(b)) => Texture(Box::new(a.merge(*b)?)),
 			(Model(a), Model(b)) => Model(Box::new(a.merge(*b)?)),
 			(BlockState(a), BlockState(b)) => BlockState(Box::new(a.merge(*b)?)),
+			(PackMeta(a), PackMeta(b)) => PackMeta(Box::new(a.merge(*b)?)),
+			(Sounds(a), Sounds(b)) => Sounds(Box::new(a.merge(*b)?)),
+			(Font(a), Font(b)) => Font(Box::new(a.merge(*b)?)),
+			(Atlas(a), Atlas(b)) => Atlas(Box::new(a.merge(*b)?)),
+			(ShaderProgram(a), ShaderProgram(b)) => ShaderProgram(Box::new(a.merge(*b)?)),
+			(ShaderSource(a), ShaderSource(b)) => ShaderSource(Box::new(a.merge(*b)?)),
+			(PostEffect(a), PostEffect(b)) => PostEffect(Box::new(a.merge(*b)?)),
+			(Particle(a), Particle(b)) => Particle(Box::new(a.merge(*b)?)),
+			(Text(a), Text(b)) => Text(Box::new(a.merge(*b)?)),
+			(Cit(a), Cit(b)) => Cit(Box::new(a.merge(*b)?)),
 			(Other(a), Other(b)) => Other(Box::new(a.merge(*b)?)),
 			_ => return Err(Error::custom(Err::IncompatibleFile)),
 		};
@@ -1,47 +1,81 @@
+use super::json::{self, KeyOrder};
 use super::{from_index, into_index, File, Kind};
-use crate::namespace::Namespace;
+use crate::config;
+use crate::namespace::{Namespace, NamespaceError};
+use crate::report::{self, Entry};
+use crate::workspace;
 use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
+use log::*;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Number, Value};
 use std::cmp::{Ord, Ordering, PartialOrd};
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use superfusion::prelude::{Error, Index, Pid, Relation};
 
 pub struct Model {
 	pid: Pid,
+	path: PathBuf,
 	data: ModelFormat,
+	order: KeyOrder,
 }
 
 impl Model {
-	pub fn new(path: impl AsRef<Path>, pid: Pid) -> Result<Self> {
-		let reader = std::fs::File::open(path).with_context(|| "Reading model file")?;
-		let data = serde_json::from_reader(reader).with_context(|| "Parsing model file")?;
-		let result = Self { data, pid };
+	pub fn new(path: impl Into<PathBuf>, pid: Pid) -> Result<Self> {
+		let path = path.into();
+		let reader = std::fs::File::open(&path).with_context(|| "Reading model file")?;
+		let (data, order) = json::from_reader(reader).with_context(|| "Parsing model file")?;
+		check_variables(&path, &data);
+		let result = Self {
+			data,
+			path,
+			pid,
+			order,
+		};
 		Ok(result)
 	}
 }
 
+impl Model {
+	pub fn into_bytes(self) -> Result<Vec<u8>> {
+		json::to_vec(&self.data, &self.order)
+	}
+}
+
 impl File for Model {
 	fn relation(&self) -> Vec<Relation> {
 		let pid = self.pid;
 		let mut result = vec![];
 
 		if let Some(parent) = &self.data.parent {
-			let parent = into_index(Kind::Model, parent, pid);
+			let parent = into_index(Kind::Model, &parent.namespace, pid);
 			result.push(parent);
 		}
 
 		if let Some(textures) = &self.data.textures {
-			for texture in textures.0.values() {
+			for texture in textures.resources() {
 				let index = into_index(Kind::Texture, texture, pid);
 				result.push(index);
 			}
 		}
 
+		if let Some(elements) = &self.data.elements {
+			for texture in face_textures(elements) {
+				if let TextureRef::Resource(texture) = texture {
+					let index = into_index(Kind::Texture, &texture.namespace, pid);
+					result.push(index);
+				}
+			}
+		}
+
 		if let Some(overrides) = &self.data.overrides {
 			for model in &overrides.0 {
-				let model = &model.model;
+				let model = &model.model.namespace;
 				let index = into_index(Kind::Model, model, pid);
 				result.push(index);
 			}
@@ -50,7 +84,7 @@ impl File for Model {
 		result.into_iter().map(Relation::new).collect()
 	}
 	fn data(self) -> Vec<u8> {
-		serde_json::to_vec(&self.data).unwrap_or_default()
+		super::logged(self.into_bytes())
 	}
 	fn modify_relation(mut self, from: &Index, to: &Index) -> Self
 	where
@@ -61,17 +95,32 @@ impl File for Model {
 		self
 	}
 
-	fn merge(self, mut other: Self) -> Result<Self, Error>
+	fn merge(mut self, mut other: Self) -> Result<Self, Error>
 	where
 		Self: Sized,
 	{
-		let data = self.data.merge(other.data);
+		let config = config::get();
+
+		if config.remap_custom_model_data {
+			if let (Some(a), Some(b)) = (&self.data.overrides, &mut other.data.overrides) {
+				let pack = config.pack_name(other.pid);
+				for (from, to) in a.remap(b) {
+					custom_model_data_remapped(&self.path, &pack, from, to);
+				}
+			}
+		}
+
+		let data = self.data.merge(other.data, &self.path);
 		other.data = data;
+		self.order.merge(other.order);
+		other.order = self.order;
 		Ok(other)
 	}
 }
 
 fn modify_relation(mut model: ModelFormat, from: &Index, to: &Index) -> ModelFormat {
+	let is_texture = workspace::texture_folder(from.path());
+
 	let from = match from_index(from) {
 		Ok(v) => v,
 		_ => return model,
@@ -81,18 +130,32 @@ fn modify_relation(mut model: ModelFormat, from: &Index, to: &Index) -> ModelFor
 		_ => return model,
 	};
 
-	let set_if_true = |v: &mut Namespace| {
+	let set_if_true = |v: &mut Reference| {
 		if *v == from {
-			*v = to.clone();
+			v.set(to.clone());
 		}
 	};
 
-	if let Some(parent) = &mut model.parent {
-		set_if_true(parent);
+	if is_texture {
+		if let Some(textures) = &mut model.textures {
+			textures.resources_mut().for_each(set_if_true);
+		}
+
+		if let Some(elements) = &mut model.elements {
+			for value in face_textures_mut(elements) {
+				if let Ok(TextureRef::Resource(texture)) = TextureRef::parse(value) {
+					if texture == from {
+						*value = to.to_string();
+					}
+				}
+			}
+		}
+
+		return model;
 	}
 
-	if let Some(textures) = &mut model.textures {
-		textures.inner().for_each(set_if_true);
+	if let Some(parent) = &mut model.parent {
+		set_if_true(parent);
 	}
 
 	if let Some(overrides) = &mut model.overrides {
@@ -105,10 +168,183 @@ fn modify_relation(mut model: ModelFormat, from: &Index, to: &Index) -> ModelFor
 	model
 }
 
+/// Every `texture` of every face in the model's elements
+fn face_textures(elements: &Value) -> Vec<TextureRef> {
+	let faces = elements
+		.as_array()
+		.into_iter()
+		.flatten()
+		.filter_map(|element| element.get("faces")?.as_object())
+		.flat_map(|faces| faces.values());
+
+	faces
+		.filter_map(|face| face.get("texture")?.as_str())
+		.filter_map(|texture| TextureRef::parse(texture).ok())
+		.collect()
+}
+
+fn face_textures_mut(elements: &mut Value) -> Vec<&mut String> {
+	let faces = elements
+		.as_array_mut()
+		.into_iter()
+		.flatten()
+		.filter_map(|element| element.get_mut("faces")?.as_object_mut())
+		.flat_map(|faces| faces.values_mut());
+
+	faces
+		.filter_map(|face| match face.get_mut("texture")? {
+			Value::String(texture) => Some(texture),
+			_ => None,
+		})
+		.collect()
+}
+
+/// Warn about face texture variables that cannot be resolved through the model's parent chain
+fn check_variables(path: &Path, model: &ModelFormat) {
+	if !log_enabled!(Level::Warn) {
+		return;
+	}
+
+	let variables: Vec<String> = match &model.elements {
+		Some(elements) => face_textures(elements)
+			.into_iter()
+			.filter_map(|texture| match texture {
+				TextureRef::Variable(name) => Some(name),
+				TextureRef::Resource(_) => None,
+			})
+			.collect(),
+		None => return,
+	};
+
+	if variables.is_empty() {
+		return;
+	}
+
+	let chain = TextureChain::new(model, |parent| load_parent(path, parent));
+
+	for variable in variables {
+		if chain.resolve(&variable) == Resolved::Missing {
+			warn!(
+				"[{file}] Texture variable '#{variable}' is not defined by this model or its parents",
+				file = path.display(),
+				variable = variable,
+			);
+		}
+	}
+}
+
+lazy_static! {
+	/// Parents are shared by many models, so each file is only read once
+	static ref PARENTS: Mutex<HashMap<PathBuf, Option<ParentModel>>> = Mutex::new(HashMap::new());
+}
+
+/// Load parent model from the same resourcepack as the model at `path`
+fn load_parent(path: &Path, parent: &Namespace) -> Option<ParentModel> {
+	let root = path
+		.ancestors()
+		.find(|ancestor| ancestor.ends_with("assets"))?
+		.parent()?;
+	let path = root.join(parent.to_path(Kind::Model));
+
+	let mut parents = PARENTS.lock().ok()?;
+	parents
+		.entry(path)
+		.or_insert_with_key(|path| {
+			let reader = std::fs::File::open(path).ok()?;
+			serde_json::from_reader(reader).ok()
+		})
+		.clone()
+}
+
+/// The part of a parent model that texture variables are resolved from
+#[derive(Debug, Deserialize, Clone)]
+struct ParentModel {
+	parent: Option<Namespace>,
+	textures: Option<Textures>,
+}
+
+/// Maximum depth of parent chain and variable indirection, guard against cyclic models
+const MAX_DEPTH: usize = 32;
+
+/// Texture maps from a model and its parents, ordered from the child to the root.
+struct TextureChain {
+	textures: Vec<Textures>,
+	/// Whether every parent in the chain could be loaded
+	complete: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Resolved {
+	Texture(Namespace),
+	/// The variable is not defined anywhere in the chain
+	Missing,
+	/// Part of the chain is not available (e.g. vanilla models) so it cannot be determined
+	Unknown,
+}
+
+impl TextureChain {
+	fn new<F>(model: &ModelFormat, lookup: F) -> Self
+	where
+		F: Fn(&Namespace) -> Option<ParentModel>,
+	{
+		let mut textures = vec![];
+		textures.extend(model.textures.clone());
+
+		let mut parent = model.parent.as_ref().map(|parent| parent.namespace.clone());
+		let mut visited: Vec<Namespace> = vec![];
+		let mut complete = true;
+
+		while let Some(current) = parent {
+			if visited.len() >= MAX_DEPTH || visited.contains(&current) {
+				complete = false;
+				break;
+			}
+
+			match lookup(&current) {
+				Some(model) => {
+					textures.extend(model.textures);
+					parent = model.parent;
+				}
+				None => {
+					complete = false;
+					break;
+				}
+			}
+
+			visited.push(current);
+		}
+
+		Self { textures, complete }
+	}
+
+	/// Follow `#variable` references where the child's definition take precedence over its parents
+	fn resolve(&self, variable: &str) -> Resolved {
+		let mut variable = variable.to_string();
+
+		for _ in 0..MAX_DEPTH {
+			let found = self
+				.textures
+				.iter()
+				.find_map(|textures| textures.0.get(&variable));
+
+			match found {
+				Some(TextureRef::Resource(texture)) => {
+					return Resolved::Texture(texture.namespace.clone())
+				}
+				Some(TextureRef::Variable(next)) => variable = next.clone(),
+				None if self.complete => return Resolved::Missing,
+				None => return Resolved::Unknown,
+			}
+		}
+
+		Resolved::Unknown
+	}
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct ModelFormat {
 	#[serde(skip_serializing_if = "Option::is_none")]
-	parent: Option<Namespace>,
+	parent: Option<Reference>,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	ambientocclusion: Option<bool>,
 	#[serde(skip_serializing_if = "Option::is_none")]
@@ -121,13 +357,16 @@ struct ModelFormat {
 	gui_light: Option<Side>,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	overrides: Option<Overrides>,
+	/// Fields this struct doesn't know about, e.g. Blockbench's `groups` or OptiFine extensions
+	#[serde(flatten)]
+	other: Map<String, Value>,
 }
 
 impl ModelFormat {
-	fn merge(self, other: Self) -> Self {
+	fn merge(self, other: Self, file: &Path) -> Self {
 		let overrides = match (self.overrides, other.overrides) {
 			(Some(v), None) | (None, Some(v)) => Some(v),
-			(Some(a), Some(b)) => Some(a.merge(b)),
+			(Some(a), Some(b)) => Some(a.merge(b, file)),
 			(None, None) => None,
 		};
 
@@ -139,16 +378,122 @@ impl ModelFormat {
 			elements: other.elements,
 			gui_light: other.gui_light,
 			overrides,
+			other: other.other,
 		}
 	}
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Textures(HashMap<String, Namespace>);
+/// Resource location as it is written in the model.
+///
+/// `item/handheld` and `minecraft:item/handheld` are the same model, the original form is kept unless it is renamed.
+#[derive(Debug, Clone)]
+pub struct Reference {
+	pub namespace: Namespace,
+	raw: String,
+}
+
+impl Reference {
+	fn parse(value: &str) -> Result<Self, NamespaceError> {
+		let namespace = Namespace::try_from(value)?;
+		let raw = value.to_string();
+		Ok(Self { namespace, raw })
+	}
+
+	pub fn set(&mut self, namespace: Namespace) {
+		self.raw = namespace.to_string();
+		self.namespace = namespace;
+	}
+}
+
+impl PartialEq for Reference {
+	fn eq(&self, other: &Self) -> bool {
+		self.namespace == other.namespace
+	}
+}
+
+impl PartialEq<Namespace> for Reference {
+	fn eq(&self, other: &Namespace) -> bool {
+		&self.namespace == other
+	}
+}
+
+impl fmt::Display for Reference {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.namespace)
+	}
+}
+
+impl Serialize for Reference {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&self.raw)
+	}
+}
+
+impl<'de> Deserialize<'de> for Reference {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let value = String::deserialize(deserializer)?;
+		Self::parse(&value).map_err(de::Error::custom)
+	}
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct Textures(IndexMap<String, TextureRef>);
 
 impl Textures {
-	fn inner(&mut self) -> impl Iterator<Item = &mut Namespace> {
-		self.0.values_mut()
+	fn resources(&self) -> impl Iterator<Item = &Namespace> {
+		self.0.values().filter_map(TextureRef::resource)
+	}
+
+	fn resources_mut(&mut self) -> impl Iterator<Item = &mut Reference> {
+		self.0.values_mut().filter_map(|texture| match texture {
+			TextureRef::Resource(namespace) => Some(namespace),
+			TextureRef::Variable(_) => None,
+		})
+	}
+}
+
+/// Texture value inside a model, either a reference to another texture variable (`#layer0`) or an actual texture.
+#[derive(Debug, Clone, PartialEq)]
+enum TextureRef {
+	Variable(String),
+	Resource(Reference),
+}
+
+impl TextureRef {
+	fn parse(value: &str) -> Result<Self, NamespaceError> {
+		match value.strip_prefix('#') {
+			Some(name) => Ok(Self::Variable(name.to_string())),
+			None => Reference::parse(value).map(Self::Resource),
+		}
+	}
+
+	fn resource(&self) -> Option<&Namespace> {
+		match self {
+			Self::Resource(reference) => Some(&reference.namespace),
+			Self::Variable(_) => None,
+		}
+	}
+}
+
+impl fmt::Display for TextureRef {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Variable(name) => write!(f, "#{}", name),
+			Self::Resource(reference) => write!(f, "{}", reference.raw),
+		}
+	}
+}
+
+impl Serialize for TextureRef {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&self.to_string())
+	}
+}
+
+impl<'de> Deserialize<'de> for TextureRef {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let value = String::deserialize(deserializer)?;
+		Self::parse(&value).map_err(de::Error::custom)
 	}
 }
 
@@ -168,72 +513,428 @@ impl Overrides {
 		self.0.iter_mut()
 	}
 
-	fn merge(self, mut other: Self) -> Self {
+	fn merge(self, mut other: Self, file: &Path) -> Self {
+		for item in &other.0 {
+			let collision = self.0.iter().find(|previous| {
+				previous.predicate == item.predicate && previous.model != item.model
+			});
+
+			if let Some(previous) = collision {
+				predicate_conflict(
+					file,
+					&item.predicate,
+					&previous.model.namespace,
+					&item.model.namespace,
+				);
+			}
+		}
+
 		let mut inner = self.0;
+		other.0.retain(|item| !inner.contains(item));
 		inner.append(&mut other.0);
-		inner.sort();
+		// Stable sort so the later pack win when the predicates are the same
+		inner.sort_by(|a, b| a.predicate.cmp(&b.predicate));
 		Self(inner)
 	}
+
+	fn custom_model_data(&self) -> BTreeSet<usize> {
+		self.0
+			.iter()
+			.filter_map(|item| item.predicate.custom_model_data())
+			.collect()
+	}
+
+	fn with_custom_model_data(&self, value: usize) -> Vec<&Override> {
+		self.0
+			.iter()
+			.filter(|item| item.predicate.custom_model_data() == Some(value))
+			.collect()
+	}
+
+	/// Move CustomModelData values of `other` that are already used differently by this overrides to unused values.
+	///
+	/// Every override sharing the same value is moved together so multi-override items like bows keep working.
+	/// Return the list of `(from, to)` mapping.
+	fn remap(&self, other: &mut Self) -> Vec<(usize, usize)> {
+		let used = self.custom_model_data();
+		let theirs = other.custom_model_data();
+
+		let mut next = used.union(&theirs).max().map_or(1, |max| max + 1);
+		let mut mapping = vec![];
+
+		for &value in used.intersection(&theirs) {
+			let ours = self.with_custom_model_data(value);
+			let theirs = other.with_custom_model_data(value);
+			let identical =
+				ours.len() == theirs.len() && theirs.iter().all(|item| ours.contains(item));
+
+			if !identical {
+				mapping.push((value, next));
+				next += 1;
+			}
+		}
+
+		for item in other.inner() {
+			let value = item.predicate.custom_model_data();
+			if let Some(&(_, to)) = mapping.iter().find(|(from, _)| Some(*from) == value) {
+				item.predicate.set_custom_model_data(to);
+			}
+		}
+
+		mapping
+	}
+}
+
+fn custom_model_data_remapped(file: &Path, pack: &str, from: usize, to: usize) {
+	info!(
+		"[{file}] Remap CustomModelData {from} of '{pack}' to {to}",
+		file = file.display(),
+		from = from,
+		to = to,
+		pack = pack,
+	);
+	report::record(Entry::CustomModelData {
+		path: report::asset_path(file),
+		pack: pack.to_string(),
+		from,
+		to,
+	});
+}
+
+fn predicate_conflict(file: &Path, predicate: &Predicate, previous: &Namespace, model: &Namespace) {
+	let predicate = serde_json::to_string(predicate).unwrap_or_default();
+	warn!(
+		"[{file}] Predicate {predicate} is used by both '{previous}' and '{model}'",
+		file = file.display(),
+		predicate = predicate,
+		previous = previous,
+		model = model,
+	);
+	report::record(Entry::OverridePredicate {
+		path: report::asset_path(file),
+		predicate,
+		previous: previous.to_string(),
+		model: model.to_string(),
+	});
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 struct Override {
 	predicate: Predicate,
-	model: Namespace,
+	model: Reference,
 }
 
-impl Eq for Override {}
+const CUSTOM_MODEL_DATA: &str = "custom_model_data";
 
-impl PartialOrd for Override {
-	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-		self.predicate.partial_cmp(&other.predicate)
+/// Override predicates keyed by their name.
+///
+/// The game use an override when every item property is greater than or equal to its predicate,
+/// so a missing predicate is the same as `0` and the last matching override win.
+#[derive(Debug, Deserialize, Serialize)]
+struct Predicate(IndexMap<String, Number>);
+
+impl Predicate {
+	fn get(&self, key: &str) -> f64 {
+		self.0.get(key).and_then(Number::as_f64).unwrap_or(0.0)
 	}
-}
 
-impl Ord for Override {
-	fn cmp(&self, other: &Self) -> Ordering {
-		self.predicate
-			.partial_cmp(&other.predicate)
-			.unwrap_or_else(|| self.model.cmp(&other.model))
+	fn custom_model_data(&self) -> Option<usize> {
+		self.0
+			.get(CUSTOM_MODEL_DATA)
+			.and_then(Number::as_u64)
+			.map(|value| value as usize)
+	}
+
+	fn set_custom_model_data(&mut self, value: usize) {
+		self.0
+			.insert(CUSTOM_MODEL_DATA.to_string(), Number::from(value as u64));
 	}
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
-struct Predicate {
-	#[serde(skip_serializing_if = "Option::is_none")]
-	angle: Option<f64>,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	blocking: Option<usize>,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	broken: Option<usize>,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	cast: Option<usize>,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	cooldown: Option<f64>,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	damage: Option<f64>,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	damaged: Option<usize>,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	lefthand: Option<usize>,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	pull: Option<f64>,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	pulling: Option<usize>,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	charge: Option<usize>,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	fireworks: Option<usize>,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	throwing: Option<usize>,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	time: Option<f64>,
-	#[serde(skip_serializing_if = "Option::is_none")]
-	custom_model_data: Option<usize>,
+impl PartialEq for Predicate {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
 }
 
+impl Eq for Predicate {}
+
 impl PartialOrd for Predicate {
 	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-		self.custom_model_data.partial_cmp(&other.custom_model_data)
+		Some(self.cmp(other))
+	}
+}
+
+/// Compare predicates value by value with `custom_model_data` being the most significant and the rest in alphabetical order.
+///
+/// When every value of one predicate is less than or equal to the other's, it will always be sorted first.
+/// This make the more specific override (e.g. `pulling` + `pull`) come after the general one (e.g. `pulling`),
+/// and every override of a CustomModelData come after the overrides of lower CustomModelData.
+impl Ord for Predicate {
+	fn cmp(&self, other: &Self) -> Ordering {
+		let keys: BTreeSet<&str> = self
+			.0
+			.keys()
+			.chain(other.0.keys())
+			.map(String::as_str)
+			.filter(|&key| key != CUSTOM_MODEL_DATA)
+			.collect();
+
+		std::iter::once(CUSTOM_MODEL_DATA)
+			.chain(keys)
+			.map(|key| {
+				self.get(key)
+					.partial_cmp(&other.get(key))
+					.unwrap_or(Ordering::Equal)
+			})
+			.find(|&ordering| ordering != Ordering::Equal)
+			.unwrap_or(Ordering::Equal)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	fn overrides(value: Value) -> Overrides {
+		serde_json::from_value(value).unwrap()
+	}
+
+	#[test]
+	fn remap_custom_model_data() {
+		let a = overrides(json!([
+			{ "predicate": { "custom_model_data": 1 }, "model": "alpha:item/wand" },
+			{ "predicate": { "custom_model_data": 2 }, "model": "alpha:item/staff" }
+		]));
+		let mut b = overrides(json!([
+			{ "predicate": { "custom_model_data": 1 }, "model": "beta:item/bow" },
+			{ "predicate": { "custom_model_data": 1, "pulling": 1 }, "model": "beta:item/bow_pulling" },
+			{ "predicate": { "custom_model_data": 2 }, "model": "alpha:item/staff" }
+		]));
+
+		let mapping = a.remap(&mut b);
+		assert_eq!(mapping, vec![(1, 3)]);
+		assert_eq!(b.custom_model_data(), vec![2, 3].into_iter().collect());
+
+		let result = a.merge(b, Path::new("stick.json"));
+		assert_eq!(result.0.len(), 4);
+	}
+
+	fn models(overrides: &Overrides) -> Vec<String> {
+		overrides
+			.0
+			.iter()
+			.map(|item| item.model.to_string())
+			.collect()
+	}
+
+	#[test]
+	fn merge_bow_overrides() {
+		let a = overrides(json!([
+			{ "predicate": { "pulling": 1 }, "model": "item/bow_pulling_0" },
+			{ "predicate": { "pulling": 1, "pull": 0.65 }, "model": "item/bow_pulling_1" },
+			{ "predicate": { "pulling": 1, "pull": 0.9 }, "model": "item/bow_pulling_2" }
+		]));
+		let b = overrides(json!([
+			{ "predicate": { "custom_model_data": 1, "pulling": 1, "pull": 0.9 }, "model": "boomber:item/bow_pulling_2" },
+			{ "predicate": { "custom_model_data": 1 }, "model": "boomber:item/bow" },
+			{ "predicate": { "custom_model_data": 1, "pulling": 1 }, "model": "boomber:item/bow_pulling_0" }
+		]));
+
+		let result = a.merge(b, Path::new("bow.json"));
+		assert_eq!(
+			models(&result),
+			vec![
+				"minecraft:item/bow_pulling_0",
+				"minecraft:item/bow_pulling_1",
+				"minecraft:item/bow_pulling_2",
+				"boomber:item/bow",
+				"boomber:item/bow_pulling_0",
+				"boomber:item/bow_pulling_2",
+			]
+		);
+	}
+
+	#[test]
+	fn merge_crossbow_overrides() {
+		let a = overrides(json!([
+			{ "predicate": { "charged": 1, "firework": 1 }, "model": "item/crossbow_firework" },
+			{ "predicate": { "pulling": 1 }, "model": "item/crossbow_pulling_0" }
+		]));
+		let b = overrides(json!([
+			{ "predicate": { "charged": 1 }, "model": "item/crossbow_arrow" },
+			{ "predicate": { "pulling": 1, "pull": 0.58 }, "model": "item/crossbow_pulling_1" }
+		]));
+
+		let result = a.merge(b, Path::new("crossbow.json"));
+		assert_eq!(
+			models(&result),
+			vec![
+				"minecraft:item/crossbow_pulling_0",
+				"minecraft:item/crossbow_pulling_1",
+				"minecraft:item/crossbow_arrow",
+				"minecraft:item/crossbow_firework",
+			]
+		);
+		assert_eq!(
+			serde_json::to_value(&result.0[3].predicate).unwrap(),
+			json!({ "charged": 1, "firework": 1 })
+		);
+	}
+
+	#[test]
+	fn merge_damaged_tool_overrides() {
+		let a = overrides(json!([
+			{ "predicate": { "damaged": 1 }, "model": "item/broken_sword" },
+			{ "predicate": { "damaged": 0, "damage": 0.5 }, "model": "item/half_sword" }
+		]));
+		let b = overrides(json!([
+			{ "predicate": { "damage": 0.25 }, "model": "item/quarter_sword" },
+			{ "predicate": { "custom_model_data": 7, "damage": 0.5 }, "model": "boomber:item/half_sword" }
+		]));
+
+		let result = a.merge(b, Path::new("diamond_sword.json"));
+		assert_eq!(
+			models(&result),
+			vec![
+				"minecraft:item/broken_sword",
+				"minecraft:item/quarter_sword",
+				"minecraft:item/half_sword",
+				"boomber:item/half_sword",
+			]
+		);
+	}
+
+	#[test]
+	fn round_trip_blockbench_model() {
+		let source = r##"{"credit":"Made with Blockbench","parent":"item/handheld","texture_size":[32,32],"textures":{"0":"boomber:item/katana","1":"item/iron_ingot","particle":"boomber:item/katana"},"elements":[{"name":"blade","from":[7.5,0,7.5],"to":[8.5,16,8.5],"rotation":{"angle":-22.5,"axis":"z","origin":[8,8,8]},"faces":{"north":{"uv":[0,0,1,16],"texture":"#0"},"up":{"uv":[0,0,1,1],"rotation":90,"texture":"#0","tintindex":0}}}],"display":{"thirdperson_righthand":{"rotation":[0,-90,55],"translation":[0,4,0.5],"scale":[0.85,0.85,0.85]}},"groups":[{"name":"katana","origin":[8,8,8],"color":0,"children":[0]}]}"##;
+
+		let (data, order): (ModelFormat, KeyOrder) = json::from_reader(source.as_bytes()).unwrap();
+		let model = Model {
+			pid: Pid::new(0),
+			path: PathBuf::from("katana.json"),
+			data,
+			order,
+		};
+
+		let from = Index::new(Pid::new(0), "assets/boomber/textures/item/katana.png");
+		let to = Index::new(Pid::new(0), "assets/boomber/textures/item/katana_0.png");
+		let result = model.modify_relation(&from, &to).data();
+
+		let expect = source.replace("boomber:item/katana", "boomber:item/katana_0");
+		assert_eq!(String::from_utf8(result).unwrap(), expect);
+	}
+
+	#[test]
+	fn keep_predicate_order() {
+		let load = |pid: usize, source: &str| {
+			let (data, order) = json::from_reader(source.as_bytes()).unwrap();
+			Model {
+				pid: Pid::new(pid),
+				path: PathBuf::from("bow.json"),
+				data,
+				order,
+			}
+		};
+		let a = load(
+			0,
+			r#"{"parent":"item/generated","overrides":[{"predicate":{"pulling":1,"pull":0.65},"model":"item/bow_pulling_1"}]}"#,
+		);
+		let b = load(
+			1,
+			r#"{"overrides":[{"model":"boomber:item/bow","predicate":{"pulling":1,"custom_model_data":1}}],"parent":"item/generated"}"#,
+		);
+
+		let result = a.merge(b).unwrap().data();
+		assert_eq!(
+			String::from_utf8(result).unwrap(),
+			r#"{"parent":"item/generated","overrides":[{"predicate":{"pulling":1,"pull":0.65},"model":"item/bow_pulling_1"},{"predicate":{"pulling":1,"custom_model_data":1},"model":"boomber:item/bow"}]}"#
+		);
+	}
+
+	fn model(value: Value) -> ModelFormat {
+		serde_json::from_value(value).unwrap()
+	}
+
+	fn parent(value: Value) -> ParentModel {
+		serde_json::from_value(value).unwrap()
+	}
+
+	#[test]
+	fn texture_variable_is_not_a_relation() {
+		let model = Model {
+			pid: Pid::new(0),
+			path: PathBuf::from("sword.json"),
+			data: model(json!({
+				"textures": { "layer0": "boomber:item/sword", "particle": "#layer0" },
+				"elements": [{ "faces": {
+					"north": { "texture": "#layer0" },
+					"south": { "texture": "boomber:item/sword_side" }
+				} }]
+			})),
+			order: KeyOrder::default(),
+		};
+
+		let relation: Vec<PathBuf> = model
+			.relation()
+			.into_iter()
+			.map(|relation| relation.index().path().to_owned())
+			.collect();
+		assert_eq!(
+			relation,
+			vec![
+				PathBuf::from("assets/boomber/textures/item/sword.png"),
+				PathBuf::from("assets/boomber/textures/item/sword_side.png"),
+			]
+		);
+
+		let from = Index::new(Pid::new(0), "assets/boomber/textures/item/sword_side.png");
+		let to = Index::new(Pid::new(0), "assets/boomber/textures/item/sword_side_0.png");
+		let result = model.modify_relation(&from, &to);
+		assert_eq!(
+			serde_json::to_value(&result.data).unwrap(),
+			json!({
+				"textures": { "layer0": "boomber:item/sword", "particle": "#layer0" },
+				"elements": [{ "faces": {
+					"north": { "texture": "#layer0" },
+					"south": { "texture": "boomber:item/sword_side_0" }
+				} }]
+			})
+		);
+	}
+
+	#[test]
+	fn resolve_texture_through_parents() {
+		let child = model(json!({
+			"parent": "boomber:block/base",
+			"textures": { "side": "boomber:block/side", "particle": "#top" }
+		}));
+		let lookup = |namespace: &Namespace| match namespace.to_string().as_str() {
+			"boomber:block/base" => Some(parent(json!({
+				"parent": "boomber:block/root",
+				"textures": { "side": "boomber:block/base_side", "top": "#side" }
+			}))),
+			"boomber:block/root" => Some(parent(json!({}))),
+			_ => None,
+		};
+
+		let chain = TextureChain::new(&child, lookup);
+		let side = Resolved::Texture(Namespace::new("boomber", "block/side"));
+		assert_eq!(chain.resolve("particle"), side);
+		assert_eq!(chain.resolve("bottom"), Resolved::Missing);
+
+		let vanilla = model(json!({ "parent": "block/cube_all" }));
+		let chain = TextureChain::new(&vanilla, lookup);
+		assert_eq!(chain.resolve("all"), Resolved::Unknown);
+	}
+
+	#[test]
+	fn cyclic_parent() {
+		let child = model(json!({ "parent": "boomber:block/b" }));
+		let lookup = |_: &Namespace| Some(parent(json!({ "parent": "boomber:block/b" })));
+
+		let chain = TextureChain::new(&child, lookup);
+		assert_eq!(chain.resolve("x"), Resolved::Unknown);
 	}
 }
@@ -10,7 +10,7 @@ pub struct Other {
 impl Other {
 	pub fn new(path: impl AsRef<Path>) -> Result<Self> {
 		let path = path.as_ref();
-		let data = std::fs::read(&path)
+		let data = std::fs::read(path)
 			.with_context(|| format!("Fail to read file at {}", path.display()))?;
 		let result = Self { data };
 		Ok(result)
@@ -0,0 +1,216 @@
+use super::json::{self, KeyOrder};
+use super::File;
+use crate::config;
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::path::Path;
+use superfusion::prelude::{Error, Index, Pid, Relation};
+
+pub struct PackMeta {
+	sources: Vec<Pid>,
+	data: PackMetaFormat,
+	order: KeyOrder,
+}
+
+impl PackMeta {
+	pub fn new(path: impl AsRef<Path>, pid: Pid) -> Result<Self> {
+		let reader = std::fs::File::open(path).with_context(|| "Reading pack metadata file")?;
+		let (data, order) =
+			json::from_reader(reader).with_context(|| "Parsing pack metadata file")?;
+		let result = Self {
+			sources: vec![pid],
+			data,
+			order,
+		};
+		Ok(result)
+	}
+}
+
+impl PackMeta {
+	pub fn into_bytes(self) -> Result<Vec<u8>> {
+		json::to_vec(&self.data, &self.order)
+	}
+}
+
+impl File for PackMeta {
+	fn relation(&self) -> Vec<Relation> {
+		vec![]
+	}
+	fn data(self) -> Vec<u8> {
+		super::logged(self.into_bytes())
+	}
+	fn modify_relation(self, _: &Index, _: &Index) -> Self
+	where
+		Self: Sized,
+	{
+		self
+	}
+	fn merge(mut self, other: Self) -> Result<Self, Error>
+	where
+		Self: Sized,
+	{
+		let config = config::get();
+
+		self.sources.extend(other.sources);
+		self.order.merge(other.order);
+		self.data = self.data.merge(other.data, config.pack_format);
+
+		let names: Vec<String> = self
+			.sources
+			.iter()
+			.map(|&pid| config.pack_name(pid))
+			.collect();
+		self.data.pack.description = description(&names);
+
+		Ok(self)
+	}
+}
+
+fn description(names: &[String]) -> Value {
+	Value::String(format!("Merged from {}", names.join(", ")))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct PackMetaFormat {
+	pack: PackSection,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	filter: Option<Filter>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	overlays: Option<Overlays>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	language: Option<IndexMap<String, Value>>,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+impl PackMetaFormat {
+	fn merge(self, other: Self, pack_format: Option<u32>) -> Self {
+		let language = match (self.language, other.language) {
+			(Some(mut a), Some(b)) => {
+				a.extend(b);
+				Some(a)
+			}
+			(a, b) => b.or(a),
+		};
+
+		let mut rest = self.other;
+		rest.extend(other.other);
+
+		Self {
+			pack: self.pack.merge(other.pack, pack_format),
+			filter: merge_option(self.filter, other.filter, Filter::merge),
+			overlays: merge_option(self.overlays, other.overlays, Overlays::merge),
+			language,
+			other: rest,
+		}
+	}
+}
+
+fn merge_option<T>(a: Option<T>, b: Option<T>, merge: impl FnOnce(T, T) -> T) -> Option<T> {
+	match (a, b) {
+		(Some(a), Some(b)) => Some(merge(a, b)),
+		(a, b) => b.or(a),
+	}
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct PackSection {
+	pack_format: u32,
+	#[serde(default)]
+	description: Value,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+impl PackSection {
+	fn merge(self, other: Self, pack_format: Option<u32>) -> Self {
+		let pack_format = pack_format.unwrap_or_else(|| self.pack_format.max(other.pack_format));
+
+		let mut rest = self.other;
+		rest.extend(other.other);
+
+		Self {
+			pack_format,
+			description: other.description,
+			other: rest,
+		}
+	}
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Filter {
+	block: Vec<Value>,
+}
+
+impl Filter {
+	fn merge(self, other: Self) -> Self {
+		Self {
+			block: union(self.block, other.block),
+		}
+	}
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Overlays {
+	entries: Vec<Value>,
+}
+
+impl Overlays {
+	fn merge(self, other: Self) -> Self {
+		Self {
+			entries: union(self.entries, other.entries),
+		}
+	}
+}
+
+/// Append `b` to `a` while skipping values that already exist
+fn union(mut a: Vec<Value>, b: Vec<Value>) -> Vec<Value> {
+	for value in b {
+		if !a.contains(&value) {
+			a.push(value);
+		}
+	}
+	a
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	fn pack_meta(value: Value) -> PackMetaFormat {
+		serde_json::from_value(value).unwrap()
+	}
+
+	#[test]
+	fn merge_pack_meta() {
+		let a = pack_meta(json!({
+			"pack": { "pack_format": 6, "description": "Alpha" },
+			"language": { "foo_bar": { "name": "Foo", "region": "Bar", "bidirectional": false } },
+			"filter": { "block": [{ "namespace": "minecraft", "path": "shaders/.*" }] }
+		}));
+		let b = pack_meta(json!({
+			"pack": { "pack_format": 15, "description": { "text": "Beta" } },
+			"filter": { "block": [
+				{ "namespace": "minecraft", "path": "shaders/.*" },
+				{ "namespace": "realms" }
+			] }
+		}));
+
+		let result = a.merge(b, None);
+		assert_eq!(result.pack.pack_format, 15);
+		assert_eq!(result.language.unwrap().len(), 1);
+		assert_eq!(result.filter.unwrap().block.len(), 2);
+	}
+
+	#[test]
+	fn merge_pack_meta_with_configured_format() {
+		let a = pack_meta(json!({ "pack": { "pack_format": 6, "description": "" } }));
+		let b = pack_meta(json!({ "pack": { "pack_format": 15, "description": "" } }));
+
+		let result = a.merge(b, Some(9));
+		assert_eq!(result.pack.pack_format, 9);
+	}
+}
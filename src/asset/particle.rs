@@ -0,0 +1,173 @@
+use super::json::{self, KeyOrder};
+use super::{from_index, into_index, File, Kind};
+use crate::namespace::Namespace;
+use crate::report::{self, Entry};
+use crate::workspace;
+use anyhow::{Context, Result};
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use superfusion::prelude::{Error, Index, Pid, Relation};
+
+/// Particle definition, list the sprites in `textures/particle` that the particle is animated with.
+pub struct Particle {
+	pid: Pid,
+	path: PathBuf,
+	data: ParticleFormat,
+	order: KeyOrder,
+}
+
+impl Particle {
+	pub fn new(path: impl Into<PathBuf>, pid: Pid) -> Result<Self> {
+		let path = path.into();
+		let reader = std::fs::File::open(&path).with_context(|| "Reading particle file")?;
+		let (data, order) = json::from_reader(reader).with_context(|| "Parsing particle file")?;
+		let result = Self {
+			pid,
+			path,
+			data,
+			order,
+		};
+		Ok(result)
+	}
+}
+
+impl Particle {
+	pub fn into_bytes(self) -> Result<Vec<u8>> {
+		json::to_vec(&self.data, &self.order)
+	}
+}
+
+impl File for Particle {
+	fn relation(&self) -> Vec<Relation> {
+		let pid = self.pid;
+
+		self.data
+			.textures()
+			.filter_map(|sprite| sprite_texture(sprite))
+			.map(|texture| into_index(Kind::Texture, &texture, pid))
+			.map(Relation::new)
+			.collect()
+	}
+	fn data(self) -> Vec<u8> {
+		super::logged(self.into_bytes())
+	}
+	fn modify_relation(mut self, from: &Index, to: &Index) -> Self
+	where
+		Self: Sized,
+	{
+		if !workspace::texture_folder(from.path()) {
+			return self;
+		}
+
+		let (from, to) = match (from_index(from), from_index(to)) {
+			(Ok(from), Ok(to)) => (from, to),
+			_ => return self,
+		};
+
+		let sprite = match texture_sprite(&to) {
+			Some(sprite) => sprite,
+			None => return self,
+		};
+
+		for texture in self.data.textures.iter_mut().flatten() {
+			if sprite_texture(texture).as_ref() == Some(&from) {
+				*texture = sprite.clone();
+			}
+		}
+
+		self
+	}
+	/// Sprites are animation frames in order, so they cannot be combined; the newer list is used
+	fn merge(mut self, other: Self) -> Result<Self, Error> {
+		if let (Some(previous), Some(textures)) = (&self.data.textures, &other.data.textures) {
+			if previous != textures {
+				sprite_conflict(&self.path, previous, textures);
+			}
+		}
+
+		self.data.textures = other.data.textures.or(self.data.textures);
+		self.data.other.extend(other.data.other);
+		self.order.merge(other.order);
+		Ok(self)
+	}
+}
+
+fn sprite_conflict(file: &Path, previous: &[String], textures: &[String]) {
+	warn!(
+		"[{file}] Particle sprites {previous:?} are replaced with {textures:?}",
+		file = file.display(),
+		previous = previous,
+		textures = textures,
+	);
+	report::record(Entry::ParticleSprites {
+		path: report::asset_path(file),
+		previous: previous.to_vec(),
+		textures: textures.to_vec(),
+	});
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ParticleFormat {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	textures: Option<Vec<String>>,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+impl ParticleFormat {
+	fn textures(&self) -> impl Iterator<Item = &String> {
+		self.textures.iter().flatten()
+	}
+}
+
+/// Texture of a sprite, `boomber:spark` → `boomber:particle/spark`
+fn sprite_texture(sprite: &str) -> Option<Namespace> {
+	let sprite = Namespace::try_from(sprite).ok()?;
+	let path = Path::new("particle").join(sprite.path);
+	Some(Namespace::new(sprite.header, path))
+}
+
+fn texture_sprite(texture: &Namespace) -> Option<String> {
+	let path = texture.path.strip_prefix("particle").ok()?;
+	Some(Namespace::new(texture.header.as_str(), path).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	fn particle(pid: usize, value: Value) -> Particle {
+		Particle {
+			pid: Pid::new(pid),
+			path: PathBuf::from("assets/boomber/particles/spark.json"),
+			data: serde_json::from_value(value).unwrap(),
+			order: KeyOrder::default(),
+		}
+	}
+
+	#[test]
+	fn rename_particle_sprites() {
+		let a = particle(0, json!({ "textures": ["boomber:spark_0"] }));
+		let b = particle(
+			1,
+			json!({ "textures": ["boomber:spark_0", "boomber:spark_1"] }),
+		);
+
+		let from = Index::new(Pid::new(1), "assets/boomber/textures/particle/spark_1.png");
+		let to = Index::new(
+			Pid::new(1),
+			"assets/boomber/textures/particle/spark_1_1.png",
+		);
+		let b = b.modify_relation(&from, &to);
+
+		let result = a.merge(b).unwrap();
+		assert_eq!(
+			serde_json::to_value(&result.data).unwrap(),
+			json!({ "textures": ["boomber:spark_0", "boomber:spark_1_1"] })
+		);
+	}
+}
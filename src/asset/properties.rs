@@ -0,0 +1,36 @@
+/// A single line of `key=value` file such as legacy language files and OptiFine properties.
+#[derive(Debug, PartialEq)]
+pub enum Line {
+	Entry {
+		key: String,
+		value: String,
+	},
+	/// Comment, blank line or anything else that is kept as-is
+	Text(String),
+}
+
+pub fn entries(lines: &[Line]) -> impl Iterator<Item = (&String, &String)> {
+	lines.iter().filter_map(|line| match line {
+		Line::Entry { key, value } => Some((key, value)),
+		Line::Text(_) => None,
+	})
+}
+
+pub fn write(lines: &[Line]) -> String {
+	let mut result = String::new();
+
+	for line in lines {
+		match line {
+			// Values from JSON files can contain newline which would end the entry early
+			Line::Entry { key, value } => {
+				result.push_str(key);
+				result.push('=');
+				result.push_str(&value.replace('\n', "\\n"));
+			}
+			Line::Text(text) => result.push_str(text),
+		}
+		result.push('\n');
+	}
+
+	result
+}
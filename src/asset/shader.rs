@@ -0,0 +1,408 @@
+use super::json::{self, KeyOrder};
+use super::{normalize, rename_reference, File};
+use crate::namespace::Namespace;
+use crate::report;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use superfusion::prelude::{Error, Index, Pid, Relation};
+
+/// Shader program in `shaders/core` or `shaders/program`, refer to its vertex and fragment shader.
+pub struct ShaderProgram {
+	pid: Pid,
+	/// Folder that the program's shaders are in, e.g. `shaders/core`
+	folder: PathBuf,
+	data: ProgramFormat,
+	order: KeyOrder,
+}
+
+impl ShaderProgram {
+	pub fn new(path: impl Into<PathBuf>, pid: Pid) -> Result<Self> {
+		let path = path.into();
+		let reader = std::fs::File::open(&path).with_context(|| "Reading shader program")?;
+		let (data, order) = json::from_reader(reader).with_context(|| "Parsing shader program")?;
+		let folder = shader_folder(&report::asset_path(&path));
+		let result = Self {
+			pid,
+			folder,
+			data,
+			order,
+		};
+		Ok(result)
+	}
+
+	pub fn into_bytes(self) -> Result<Vec<u8>> {
+		json::to_vec(&self.data, &self.order)
+	}
+
+	fn shaders(&mut self) -> impl Iterator<Item = (&mut String, &'static str)> {
+		let vertex = self.data.vertex.as_mut().map(|shader| (shader, "vsh"));
+		let fragment = self.data.fragment.as_mut().map(|shader| (shader, "fsh"));
+		vertex.into_iter().chain(fragment)
+	}
+}
+
+impl File for ShaderProgram {
+	fn relation(&self) -> Vec<Relation> {
+		let shaders = [(&self.data.vertex, "vsh"), (&self.data.fragment, "fsh")];
+
+		shaders
+			.iter()
+			.filter_map(|(shader, extension)| {
+				resource_path(shader.as_ref()?, &self.folder, extension)
+			})
+			.map(|path| Relation::new(Index::new(self.pid, path)))
+			.collect()
+	}
+	fn data(self) -> Vec<u8> {
+		super::logged(self.into_bytes())
+	}
+	fn modify_relation(mut self, from: &Index, to: &Index) -> Self
+	where
+		Self: Sized,
+	{
+		let folder = self.folder.clone();
+
+		for (shader, extension) in self.shaders() {
+			if resource_path(shader, &folder, extension).as_deref() == Some(from.path()) {
+				*shader = rename_reference(shader, to.path(), false);
+			}
+		}
+
+		self
+	}
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ProgramFormat {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	vertex: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	fragment: Option<String>,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+/// `.vsh`, `.fsh` or `.glsl` shader source, refer to other files with `#moj_import`.
+pub struct ShaderSource {
+	pid: Pid,
+	path: PathBuf,
+	data: Vec<u8>,
+}
+
+impl ShaderSource {
+	pub fn new(path: impl Into<PathBuf>, pid: Pid) -> Result<Self> {
+		let path = path.into();
+		let data = std::fs::read(&path).with_context(|| "Reading shader source")?;
+		let path = report::asset_path(&path);
+		let result = Self { pid, path, data };
+		Ok(result)
+	}
+
+	/// Path of the file that an import refer to
+	fn resolve(&self, import: &Import) -> Option<PathBuf> {
+		match import {
+			Import::Include(location) => resource_path(location, Path::new("shaders/include"), ""),
+			Import::Relative(path) => Some(normalize(&self.path.parent()?.join(path))),
+		}
+	}
+}
+
+impl File for ShaderSource {
+	fn relation(&self) -> Vec<Relation> {
+		let text = String::from_utf8_lossy(&self.data);
+
+		text.lines()
+			.filter_map(Import::parse)
+			.filter_map(|import| self.resolve(&import))
+			.map(|path| Relation::new(Index::new(self.pid, path)))
+			.collect()
+	}
+	fn data(self) -> Vec<u8> {
+		self.data
+	}
+	fn modify_relation(mut self, from: &Index, to: &Index) -> Self
+	where
+		Self: Sized,
+	{
+		let text = match std::str::from_utf8(&self.data) {
+			Ok(text) => text,
+			Err(_) => return self,
+		};
+
+		let mut result = String::with_capacity(text.len());
+		for line in text.split_inclusive('\n') {
+			let reference = Import::parse(line)
+				.filter(|import| self.resolve(import).as_deref() == Some(from.path()))
+				.map(|import| import.reference().to_string());
+
+			match reference {
+				Some(reference) => {
+					let renamed = rename_reference(&reference, to.path(), true);
+					result.push_str(&line.replacen(&reference, &renamed, 1));
+				}
+				None => result.push_str(line),
+			}
+		}
+
+		self.data = result.into_bytes();
+		self
+	}
+}
+
+enum Import<'a> {
+	/// `#moj_import <location>` from `shaders/include`
+	Include(&'a str),
+	/// `#moj_import "path"` relative to the current file
+	Relative(&'a str),
+}
+
+impl<'a> Import<'a> {
+	fn parse(line: &'a str) -> Option<Self> {
+		let rest = line.trim().strip_prefix("#moj_import")?.trim();
+
+		if let Some(location) = rest.strip_prefix('<') {
+			return Some(Self::Include(location.strip_suffix('>')?));
+		}
+
+		let path = rest.strip_prefix('"')?.strip_suffix('"')?;
+		Some(Self::Relative(path))
+	}
+
+	fn reference(&self) -> &'a str {
+		match self {
+			Self::Include(reference) | Self::Relative(reference) => reference,
+		}
+	}
+}
+
+/// Post-processing effect in `shaders/post` or `post_effect`, made of passes that use shader programs.
+pub struct PostEffect {
+	pid: Pid,
+	data: PostEffectFormat,
+	order: KeyOrder,
+}
+
+impl PostEffect {
+	pub fn new(path: impl Into<PathBuf>, pid: Pid) -> Result<Self> {
+		let path = path.into();
+		let reader = std::fs::File::open(&path).with_context(|| "Reading post effect")?;
+		let (data, order) = json::from_reader(reader).with_context(|| "Parsing post effect")?;
+		let result = Self { pid, data, order };
+		Ok(result)
+	}
+
+	pub fn into_bytes(self) -> Result<Vec<u8>> {
+		json::to_vec(&self.data, &self.order)
+	}
+}
+
+impl File for PostEffect {
+	fn relation(&self) -> Vec<Relation> {
+		self.data
+			.passes
+			.iter()
+			.flatten()
+			.flat_map(Pass::references)
+			.filter_map(|(_, path)| path)
+			.map(|path| Relation::new(Index::new(self.pid, path)))
+			.collect()
+	}
+	fn data(self) -> Vec<u8> {
+		super::logged(self.into_bytes())
+	}
+	fn modify_relation(mut self, from: &Index, to: &Index) -> Self
+	where
+		Self: Sized,
+	{
+		for pass in self.data.passes.iter_mut().flatten() {
+			let renamed: Vec<(&'static str, String)> = pass
+				.references()
+				.into_iter()
+				.filter(|(_, path)| path.as_deref() == Some(from.path()))
+				.filter_map(|(key, _)| {
+					let reference = pass.0.get(key)?.as_str()?;
+					Some((key, rename_reference(reference, to.path(), false)))
+				})
+				.collect();
+
+			for (key, reference) in renamed {
+				pass.0.insert(key.to_string(), Value::String(reference));
+			}
+		}
+
+		self
+	}
+	fn merge(mut self, other: Self) -> Result<Self, Error> {
+		self.data = self.data.merge(other.data);
+		self.order.merge(other.order);
+		Ok(self)
+	}
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct PostEffectFormat {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	targets: Option<Value>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	passes: Option<Vec<Pass>>,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+impl PostEffectFormat {
+	/// Passes of the newer effect are appended after the older ones, skipping identical passes
+	fn merge(self, other: Self) -> Self {
+		let targets = match (self.targets, other.targets) {
+			(Some(Value::Array(mut a)), Some(Value::Array(b))) => {
+				for target in b {
+					if !a.contains(&target) {
+						a.push(target);
+					}
+				}
+				Some(Value::Array(a))
+			}
+			(Some(Value::Object(mut a)), Some(Value::Object(b))) => {
+				a.extend(b);
+				Some(Value::Object(a))
+			}
+			(a, b) => b.or(a),
+		};
+
+		let passes = match (self.passes, other.passes) {
+			(Some(mut a), Some(b)) => {
+				for pass in b {
+					if !a.contains(&pass) {
+						a.push(pass);
+					}
+				}
+				Some(a)
+			}
+			(a, b) => b.or(a),
+		};
+
+		let mut rest = self.other;
+		rest.extend(other.other);
+
+		Self {
+			targets,
+			passes,
+			other: rest,
+		}
+	}
+}
+
+/// A single post effect pass, kept as-is since the format changed between versions.
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct Pass(Map<String, Value>);
+
+impl Pass {
+	/// Keys that refer to other files and the path they refer to
+	fn references(&self) -> Vec<(&'static str, Option<PathBuf>)> {
+		let reference = |key: &'static str, folder: &str, extension: &str| {
+			let location = self.0.get(key).and_then(Value::as_str);
+			let path =
+				location.and_then(|location| resource_path(location, Path::new(folder), extension));
+			(key, path)
+		};
+
+		vec![
+			reference("name", "shaders/program", "json"),
+			reference("vertex_shader", "shaders", "vsh"),
+			reference("fragment_shader", "shaders", "fsh"),
+		]
+	}
+}
+
+/// Folder of a shader file inside its namespace, e.g. `shaders/core`
+fn shader_folder(path: &Path) -> PathBuf {
+	path.components().skip(2).take(2).collect()
+}
+
+/// Path of a resource location in `folder` of its namespace, `boomber:glow` → `assets/boomber/shaders/core/glow.vsh`
+fn resource_path(location: &str, folder: &Path, extension: &str) -> Option<PathBuf> {
+	let namespace = Namespace::try_from(location).ok()?;
+	let (header, path) = namespace.raw();
+	let mut path = PathBuf::from("assets")
+		.join(header)
+		.join(folder)
+		.join(path)
+		.into_os_string();
+
+	if !extension.is_empty() {
+		path.push(".");
+		path.push(extension);
+	}
+
+	Some(PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	#[test]
+	fn rename_shader_references() {
+		let pid = Pid::new(0);
+		let from = Index::new(pid, "assets/boomber/shaders/include/glow.glsl");
+		let to = Index::new(pid, "assets/boomber/shaders/include/glow_0.glsl");
+
+		let source = ShaderSource {
+			pid,
+			path: PathBuf::from("assets/boomber/shaders/core/glow.fsh"),
+			data: b"#version 150\n#moj_import <boomber:glow.glsl>\n#moj_import \"../include/glow.glsl\"\n#moj_import <fog.glsl>\n".to_vec(),
+		};
+		assert_eq!(source.relation().len(), 3);
+		let source = source.modify_relation(&from, &to);
+		assert_eq!(
+			String::from_utf8(source.data).unwrap(),
+			"#version 150\n#moj_import <boomber:glow_0.glsl>\n#moj_import \"../include/glow_0.glsl\"\n#moj_import <fog.glsl>\n"
+		);
+
+		let program = ShaderProgram {
+			pid,
+			folder: PathBuf::from("shaders/core"),
+			data: serde_json::from_value(
+				json!({ "vertex": "boomber:glow", "fragment": "boomber:glow" }),
+			)
+			.unwrap(),
+			order: KeyOrder::default(),
+		};
+		let from = Index::new(pid, "assets/boomber/shaders/core/glow.fsh");
+		let to = Index::new(pid, "assets/boomber/shaders/core/glow_0.fsh");
+		let program = program.modify_relation(&from, &to);
+		assert_eq!(program.data.vertex.as_deref(), Some("boomber:glow"));
+		assert_eq!(program.data.fragment.as_deref(), Some("boomber:glow_0"));
+	}
+
+	#[test]
+	fn merge_post_effect() {
+		let effect = |value: Value| PostEffect {
+			pid: Pid::new(0),
+			data: serde_json::from_value(value).unwrap(),
+			order: KeyOrder::default(),
+		};
+		let blur = json!({ "name": "blur", "intarget": "minecraft:main", "outtarget": "swap" });
+		let a = effect(json!({ "targets": ["swap"], "passes": [blur] }));
+		let b = effect(json!({
+			"targets": ["swap", "glow"],
+			"passes": [blur, { "name": "boomber:glow", "intarget": "swap", "outtarget": "glow" }]
+		}));
+
+		let from = Index::new(Pid::new(0), "assets/boomber/shaders/program/glow.json");
+		let to = Index::new(Pid::new(0), "assets/boomber/shaders/program/glow_1.json");
+		let b = b.modify_relation(&from, &to);
+
+		let result = a.merge(b).unwrap();
+		assert_eq!(
+			serde_json::to_value(&result.data).unwrap(),
+			json!({
+				"targets": ["swap", "glow"],
+				"passes": [blur, { "name": "boomber:glow_1", "intarget": "swap", "outtarget": "glow" }]
+			})
+		);
+	}
+}
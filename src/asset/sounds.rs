@@ -0,0 +1,246 @@
+use super::json::{self, KeyOrder};
+use super::{from_index, into_index, File, Kind};
+use crate::namespace::Namespace;
+use crate::report::{self, Entry};
+use crate::workspace;
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use log::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use superfusion::prelude::{Error, Index, Pid, Relation};
+
+pub struct Sounds {
+	pid: Pid,
+	path: PathBuf,
+	data: IndexMap<String, SoundEvent>,
+	order: KeyOrder,
+}
+
+impl Sounds {
+	pub fn new(path: impl Into<PathBuf>, pid: Pid) -> Result<Self> {
+		let path = path.into();
+		let reader = std::fs::File::open(&path).with_context(|| "Reading sounds file")?;
+		let (data, order) = json::from_reader(reader).with_context(|| "Parsing sounds file")?;
+		let result = Self {
+			pid,
+			path,
+			data,
+			order,
+		};
+		Ok(result)
+	}
+}
+
+impl Sounds {
+	pub fn into_bytes(self) -> Result<Vec<u8>> {
+		json::to_vec(&self.data, &self.order)
+	}
+}
+
+impl File for Sounds {
+	fn relation(&self) -> Vec<Relation> {
+		let pid = self.pid;
+
+		self.data
+			.values()
+			.flat_map(SoundEvent::sounds)
+			.filter_map(Sound::file)
+			.map(|sound| into_index(Kind::Sound, &sound, pid))
+			.map(Relation::new)
+			.collect()
+	}
+	fn data(self) -> Vec<u8> {
+		super::logged(self.into_bytes())
+	}
+	fn modify_relation(mut self, from: &Index, to: &Index) -> Self
+	where
+		Self: Sized,
+	{
+		if !workspace::sound_file(from.path()) {
+			return self;
+		}
+
+		let (from, to) = match (from_index(from), from_index(to)) {
+			(Ok(from), Ok(to)) => (from, to),
+			_ => return self,
+		};
+
+		for sound in self.data.values_mut().flat_map(SoundEvent::sounds_mut) {
+			if sound.file().as_ref() == Some(&from) {
+				sound.set_name(to.to_string());
+			}
+		}
+
+		self
+	}
+	fn merge(mut self, other: Self) -> Result<Self, Error> {
+		self.order.merge(other.order);
+		for (key, event) in other.data {
+			match self.data.get_mut(&key) {
+				Some(previous) if event.replace == Some(true) => {
+					event_replaced(&self.path, &key);
+					*previous = event;
+				}
+				Some(previous) => previous.merge(event),
+				None => {
+					self.data.insert(key, event);
+				}
+			}
+		}
+
+		Ok(self)
+	}
+}
+
+fn event_replaced(file: &Path, event: &str) {
+	warn!(
+		"[{file}] Sound event '{event}' is replaced by a higher priority pack",
+		file = file.display(),
+		event = event,
+	);
+	report::record(Entry::SoundEvent {
+		path: report::asset_path(file),
+		event: event.to_string(),
+	});
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct SoundEvent {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	replace: Option<bool>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	subtitle: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	sounds: Option<Vec<Sound>>,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+impl SoundEvent {
+	fn sounds(&self) -> impl Iterator<Item = &Sound> {
+		self.sounds.iter().flatten()
+	}
+
+	fn sounds_mut(&mut self) -> impl Iterator<Item = &mut Sound> {
+		self.sounds.iter_mut().flatten()
+	}
+
+	/// Append sounds of the newer event, keeping the older one's sounds as well
+	fn merge(&mut self, other: Self) {
+		if let Some(sounds) = other.sounds {
+			let previous = self.sounds.get_or_insert_with(Vec::new);
+			for sound in sounds {
+				if !previous.contains(&sound) {
+					previous.push(sound);
+				}
+			}
+		}
+
+		self.replace = self.replace.or(other.replace);
+		self.subtitle = other.subtitle.or_else(|| self.subtitle.take());
+		self.other.extend(other.other);
+	}
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+enum Sound {
+	Name(String),
+	Object(SoundObject),
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct SoundObject {
+	name: String,
+	#[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+	kind: Option<String>,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+impl Sound {
+	/// Sound file this entry play, entries referring to another event does not have one
+	fn file(&self) -> Option<Namespace> {
+		let name = match self {
+			Self::Name(name) => name,
+			Self::Object(object) if object.kind.as_deref() == Some("event") => return None,
+			Self::Object(object) => &object.name,
+		};
+		Namespace::try_from(name.as_str()).ok()
+	}
+
+	fn set_name(&mut self, name: String) {
+		match self {
+			Self::Name(previous) => *previous = name,
+			Self::Object(object) => object.name = name,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	fn sounds(pid: usize, value: Value) -> Sounds {
+		Sounds {
+			pid: Pid::new(pid),
+			path: PathBuf::from("assets/boomber/sounds.json"),
+			data: serde_json::from_value(value).unwrap(),
+			order: KeyOrder::default(),
+		}
+	}
+
+	#[test]
+	fn merge_sound_events() {
+		let a = sounds(
+			0,
+			json!({
+				"item.explode": { "subtitle": "boom", "sounds": ["boomber:explode"] },
+				"item.fizz": { "sounds": ["boomber:fizz"] }
+			}),
+		);
+		let b = sounds(
+			1,
+			json!({
+				"item.explode": { "sounds": [
+					"boomber:explode",
+					{ "name": "boomber:big_explode", "volume": 0.5 }
+				] },
+				"item.fizz": { "replace": true, "sounds": [{ "name": "item.explode", "type": "event" }] }
+			}),
+		);
+
+		let from = Index::new(Pid::new(1), "assets/boomber/sounds/big_explode.ogg");
+		let to = Index::new(Pid::new(1), "assets/boomber/sounds/big_explode_1.ogg");
+		let b = b.modify_relation(&from, &to);
+
+		let relation: Vec<PathBuf> = b
+			.relation()
+			.into_iter()
+			.map(|relation| relation.index().path().to_owned())
+			.collect();
+		assert_eq!(
+			relation,
+			vec![
+				PathBuf::from("assets/boomber/sounds/explode.ogg"),
+				PathBuf::from("assets/boomber/sounds/big_explode_1.ogg"),
+			]
+		);
+
+		let result = a.merge(b).unwrap();
+		assert_eq!(
+			serde_json::to_value(&result.data).unwrap(),
+			json!({
+				"item.explode": { "subtitle": "boom", "sounds": [
+					"boomber:explode",
+					{ "name": "boomber:big_explode_1", "volume": 0.5 }
+				] },
+				"item.fizz": { "replace": true, "sounds": [{ "name": "item.explode", "type": "event" }] }
+			})
+		);
+	}
+}
@@ -0,0 +1,73 @@
+use super::File;
+use anyhow::{Context, Result};
+use std::path::Path;
+use superfusion::prelude::{Error, Index, Relation};
+
+/// Line-based text file such as `texts/splashes.txt`, where every line is a separate entry.
+pub struct Text {
+	lines: Vec<String>,
+}
+
+impl Text {
+	pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+		let path = path.as_ref();
+		let content = std::fs::read_to_string(path)
+			.with_context(|| format!("Fail to read text file at {}", path.display()))?;
+		let lines = content
+			.trim_start_matches('\u{feff}')
+			.lines()
+			.map(String::from)
+			.collect();
+		let result = Self { lines };
+		Ok(result)
+	}
+}
+
+impl File for Text {
+	fn relation(&self) -> Vec<Relation> {
+		vec![]
+	}
+	fn data(self) -> Vec<u8> {
+		let mut result = self.lines.join("\n");
+		result.push('\n');
+		result.into_bytes()
+	}
+	fn modify_relation(self, _: &Index, _: &Index) -> Self
+	where
+		Self: Sized,
+	{
+		self
+	}
+	/// Lines of the newer file are appended in order, blank lines are kept since they separate paragraphs
+	fn merge(mut self, other: Self) -> Result<Self, Error> {
+		for line in other.lines {
+			if line.trim().is_empty() || !self.lines.contains(&line) {
+				self.lines.push(line);
+			}
+		}
+
+		Ok(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn text(content: &str) -> Text {
+		let lines = content.lines().map(String::from).collect();
+		Text { lines }
+	}
+
+	#[test]
+	fn merge_splashes() {
+		let a = text("Also try Terraria!\nBoom!\n");
+		let b = text("Boom!\nNow with TNT!\n");
+
+		let result = a.merge(b).unwrap();
+		assert_eq!(
+			result.data(),
+			b"Also try Terraria!\nBoom!\nNow with TNT!\n".to_vec()
+		);
+	}
+}
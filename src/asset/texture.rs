@@ -1,6 +1,7 @@
 use super::File;
+use crate::workspace;
 use anyhow::{Context, Result};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use superfusion::prelude::{Index, Relation};
 
 pub struct Texture {
@@ -10,7 +11,7 @@ pub struct Texture {
 impl Texture {
 	pub fn new(path: impl AsRef<Path>) -> Result<Self> {
 		let path = path.as_ref();
-		let data = std::fs::read(&path)
+		let data = std::fs::read(path)
 			.with_context(|| "Reading texture file")?;
 		let result = Self { data };
 		Ok(result)
@@ -31,3 +32,94 @@ impl File for Texture {
 		self
 	}
 }
+
+/// OptiFine emissive, normal and specular map suffixes
+const MAPS: [&str; 3] = ["_e", "_n", "_s"];
+
+/// File that belong to a texture and must keep the same name as it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sidecar {
+	/// `.png.mcmeta` animation and texture properties
+	Meta,
+	/// OptiFine `_e`, `_n` or `_s` map
+	Map(&'static str),
+}
+
+impl Sidecar {
+	/// Kind of the sidecar and the path of the texture it might belong to
+	pub fn of(path: &Path) -> Option<(Self, PathBuf)> {
+		let cit = workspace::cit_folder(path);
+
+		if workspace::texture_meta(path) || (cit && path.to_str()?.ends_with(".png.mcmeta")) {
+			return Some((Self::Meta, path.with_extension("")));
+		}
+
+		if (!workspace::texture_folder(path) && !cit) || path.extension()? != "png" {
+			return None;
+		}
+
+		let stem = path.file_stem()?.to_str()?;
+		MAPS.iter().find_map(|&suffix| {
+			let texture = stem.strip_suffix(suffix).filter(|name| !name.is_empty())?;
+			let texture = path.with_file_name(format!("{}.png", texture));
+			Some((Self::Map(suffix), texture))
+		})
+	}
+
+	/// Path of this sidecar for the given texture
+	pub fn path(self, texture: &Path) -> PathBuf {
+		match self {
+			Self::Meta => {
+				let mut path = texture.as_os_str().to_owned();
+				path.push(".mcmeta");
+				PathBuf::from(path)
+			}
+			Self::Map(suffix) => {
+				let stem = texture.file_stem().unwrap_or_default().to_string_lossy();
+				texture.with_file_name(format!("{}{}.png", stem, suffix))
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sidecar_follow_texture() {
+		let meta = Path::new("assets/boomber/textures/item/sword.png.mcmeta");
+		let (kind, texture) = Sidecar::of(meta).unwrap();
+		assert_eq!(texture, Path::new("assets/boomber/textures/item/sword.png"));
+		assert_eq!(
+			kind.path(Path::new("assets/boomber/textures/item/sword_1.png")),
+			Path::new("assets/boomber/textures/item/sword_1.png.mcmeta")
+		);
+
+		let emissive = Path::new("assets/boomber/textures/item/sword_e.png");
+		let (kind, texture) = Sidecar::of(emissive).unwrap();
+		assert_eq!(texture, Path::new("assets/boomber/textures/item/sword.png"));
+		assert_eq!(
+			kind.path(Path::new("assets/boomber/textures/item/sword_1.png")),
+			Path::new("assets/boomber/textures/item/sword_1_e.png")
+		);
+
+		assert!(Sidecar::of(Path::new("assets/boomber/textures/item/sword.png")).is_none());
+		assert!(Sidecar::of(Path::new("assets/boomber/textures/item/_e.png")).is_none());
+	}
+
+	#[test]
+	fn cit_sidecar() {
+		let rule = Path::new("assets/minecraft/optifine/cit/sword_e.properties");
+		assert!(Sidecar::of(rule).is_none());
+
+		let emissive = Path::new("assets/minecraft/optifine/cit/sword_e.png");
+		let (_, texture) = Sidecar::of(emissive).unwrap();
+		assert_eq!(texture, Path::new("assets/minecraft/optifine/cit/sword.png"));
+
+		let meta = Path::new("assets/minecraft/optifine/cit/sword.png.mcmeta");
+		let (kind, texture) = Sidecar::of(meta).unwrap();
+		assert_eq!(kind, Sidecar::Meta);
+		assert_eq!(texture, Path::new("assets/minecraft/optifine/cit/sword.png"));
+	}
+}
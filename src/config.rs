@@ -0,0 +1,70 @@
+use crate::asset::{LangFormat, LangPolicy, LangRule};
+use lazy_static::lazy_static;
+use std::sync::RwLock;
+use superfusion::prelude::Pid;
+
+/// Merge options that need to be reachable from inside `File::merge()`.
+///
+/// It is set once before resolving the workspace.
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+	/// Name of every pack indexed by their `Pid`
+	pub pack_names: Vec<String>,
+	/// Force the output `pack_format` instead of using the highest one
+	pub pack_format: Option<u32>,
+	/// Move colliding CustomModelData of the later pack to unused values
+	pub remap_custom_model_data: bool,
+	/// Move colliding font glyphs of later packs to unused codepoints
+	pub remap_glyphs: bool,
+	/// Convert language files to the format used by `pack_format`
+	pub convert_lang: bool,
+	/// How conflicting language keys are resolved
+	pub lang_policies: Vec<LangRule>,
+	/// Pretty-print JSON files instead of minifying them
+	pub pretty: bool,
+}
+
+impl Config {
+	pub fn pack_name(&self, pid: Pid) -> String {
+		self.pack_names
+			.get(pid.value())
+			.cloned()
+			.unwrap_or_else(|| pid.to_string())
+	}
+
+	/// Format every language file should be written in, `None` keep their original format
+	pub fn lang_format(&self) -> Option<LangFormat> {
+		if !self.convert_lang {
+			return None;
+		}
+
+		self.pack_format.map(LangFormat::from_pack_format)
+	}
+
+	/// Policy of the most specific rule for this key, later rules take precedence among equally specific ones
+	pub fn lang_policy(&self, namespace: &str, key: &str) -> LangPolicy {
+		self.lang_policies
+			.iter()
+			.filter_map(|rule| Some((rule.specificity(namespace, key)?, rule)))
+			.max_by_key(|(specificity, _)| *specificity)
+			.map(|(_, rule)| rule.policy())
+			.unwrap_or_default()
+	}
+}
+
+lazy_static! {
+	static ref CONFIG: RwLock<Config> = RwLock::new(Config::default());
+}
+
+pub fn set(config: Config) {
+	if let Ok(mut current) = CONFIG.write() {
+		*current = config;
+	}
+}
+
+pub fn get() -> Config {
+	CONFIG
+		.read()
+		.map(|config| config.clone())
+		.unwrap_or_default()
+}
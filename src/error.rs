@@ -1,7 +1,20 @@
+use std::path::PathBuf;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum Error {
 	#[error("Incompatible file type for merging")]
 	IncompatibleFile,
+	#[error("Pack '{0}' listed in the pack order does not exist in the input directory")]
+	UnknownPack(String),
+	#[error("Pack '{0}' is listed more than once in the pack order")]
+	DuplicatePack(String),
+	#[error("Output path '{0}' already exists and was not created by ribozyme, use `--force` to overwrite it")]
+	UnsafeOutput(PathBuf),
+	#[error("Output path '{0}' overlaps with the input directory '{1}'")]
+	OutputOverlap(PathBuf, PathBuf),
+	#[error("Invalid language conflict policy '{0}', expected `[scope=]first-wins|last-wins|fail|keep-both`")]
+	InvalidLangPolicy(String),
+	#[error("Language key '{1}' in '{0}' is defined by multiple packs")]
+	LangConflict(PathBuf, String),
 }
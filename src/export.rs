@@ -0,0 +1,364 @@
+use super::asset::font::{self, FontGlyphs};
+use super::asset::{from_index, Sidecar};
+use super::config::{self, Config};
+use super::report::{self, Entry};
+use super::workspace;
+use super::{Asset, Workspace};
+use anyhow::{Context, Result};
+use log::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use superfusion::prelude::{File, Index, IndexMapping, Pid, Strategy, Timeline, Workspace as _};
+
+/// Deterministic replacement for `Timeline::export_to()`.
+///
+/// Superfusion export indexes in whatever order its internal `HashMap` give,
+/// which mean the pack that "win" a conflict can be different between each run.
+/// This export every output file from the lowest to the highest priority pack instead.
+pub struct Export<'a> {
+	outputs: BTreeMap<PathBuf, Vec<Source<'a>>>,
+	mapping: IndexMapping<'a>,
+	projects: HashMap<Pid, &'a Path>,
+}
+
+/// A single index that contribute to an output file.
+struct Source<'a> {
+	index: &'a Index,
+	strategy: Strategy,
+}
+
+impl<'a> Export<'a> {
+	pub fn new(timeline: &'a Timeline<'a, Workspace>) -> Result<Self> {
+		let oid = timeline.output_id();
+		let config = config::get();
+
+		let mut outputs: BTreeMap<PathBuf, Vec<Source>> = BTreeMap::new();
+		let mut mapping = HashMap::new();
+		let mut strategies = HashMap::new();
+
+		let mut indexes: Vec<(&Index, Strategy)> = timeline
+			.strategy()
+			.map(|(&index, &strategy)| (index, strategy))
+			.collect();
+		indexes.sort_by_key(|(index, _)| (index.path().to_owned(), index.pid().value()));
+
+		let known: HashSet<&Index> = indexes.iter().map(|&(index, _)| index).collect();
+
+		// Sidecars are placed after their texture has been decided, `.mcmeta` last since it can belong to a sidecar too
+		let mut sidecars = vec![];
+		indexes.retain(|&(index, strategy)| match Sidecar::of(index.path()) {
+			Some((kind, texture)) if known.contains(&Index::new(*index.pid(), &texture)) => {
+				sidecars.push((index, strategy, kind, texture));
+				false
+			}
+			_ => true,
+		});
+		sidecars.sort_by_key(|&(_, _, kind, _)| kind == Sidecar::Meta);
+
+		for (index, strategy) in indexes {
+			let output = match strategy {
+				Strategy::Rename => index.rename(Workspace::formatter)?.with_pid(oid),
+				Strategy::Replace | Strategy::Merge => index.with_pid(oid),
+			};
+			// Converted language files can meet files of the other format, they are merged like any language file
+			let (output, strategy) = match config.lang_format() {
+				Some(format) if workspace::lang_folder(output.path()) => {
+					let output = Index::new(oid, format.convert(output.path()));
+					(output, Strategy::Merge)
+				}
+				_ => (output, strategy),
+			};
+			mapping.insert(index, output);
+			strategies.insert(index, strategy);
+		}
+
+		for (index, strategy, kind, texture) in sidecars {
+			let texture = Index::new(*index.pid(), texture);
+			let output = match mapping.get(&texture) {
+				Some(output) => Index::new(oid, kind.path(output.path())),
+				None => continue,
+			};
+
+			// The sidecar is moved along with its texture, or kept in place with it
+			let strategy = match strategy {
+				_ if output.path() != index.path() => Strategy::Rename,
+				Strategy::Rename => Strategy::Replace,
+				strategy => strategy,
+			};
+			mapping.insert(index, output);
+			strategies.insert(index, strategy);
+		}
+
+		let mut sorted: Vec<(&Index, Strategy)> = strategies.into_iter().collect();
+		sorted.sort_by_key(|(index, _)| (index.path().to_owned(), index.pid().value()));
+
+		for (index, strategy) in sorted {
+			let output = &mapping[index];
+
+			if strategy == Strategy::Rename {
+				report::record(Entry::Renamed {
+					pack: config.pack_name(*index.pid()),
+					from: index.path().to_owned(),
+					to: output.path().to_owned(),
+					from_namespace: from_index(index).ok().map(|n| n.to_string()),
+					to_namespace: from_index(output).ok().map(|n| n.to_string()),
+				});
+			}
+
+			let source = Source { index, strategy };
+			outputs
+				.entry(output.path().to_owned())
+				.or_default()
+				.push(source);
+		}
+
+		// Sources of a converted language file come from differently named files, so they are ordered by priority again
+		for sources in outputs.values_mut() {
+			sources.sort_by_key(|source| source.index.pid().value());
+		}
+
+		let projects = timeline
+			.projects()
+			.map(|(&pid, &path)| (pid, path))
+			.collect();
+
+		let result = Self {
+			outputs,
+			mapping: IndexMapping::new(mapping),
+			projects,
+		};
+
+		if config.remap_glyphs {
+			result.remap_glyphs();
+		}
+
+		result.cit_conflicts();
+
+		Ok(result)
+	}
+
+	/// Save the merged resourcepack into the given `root` directory
+	pub fn export_to(&self, root: &Path) -> Result<()> {
+		for (path, sources) in &self.outputs {
+			let file = match self.resolve(path, sources)? {
+				Some(file) => file,
+				None => continue,
+			};
+
+			let path = root.join(path);
+			debug!("Write file content to {}", path.display());
+
+			if let Some(parent) = path.parent() {
+				std::fs::create_dir_all(parent)
+					.with_context(|| format!("Creating directory {}", parent.display()))?;
+			}
+
+			let data = file
+				.into_bytes()
+				.with_context(|| format!("Failed to serialize {}", path.display()))?;
+			std::fs::write(&path, data)
+				.with_context(|| format!("Writing file {}", path.display()))?;
+		}
+
+		Ok(())
+	}
+
+	/// Print what [export_to()](#method.export_to) would do without writing anything.
+	///
+	/// Files are still merged and serialized in memory so that conflicts and errors show up.
+	pub fn dry_run(&self) -> Result<()> {
+		let config = config::get();
+
+		for (path, sources) in &self.outputs {
+			println!("{}", describe(path, sources, &config));
+			if let Some(file) = self.resolve(path, sources)? {
+				file.into_bytes()
+					.with_context(|| format!("Failed to serialize {}", path.display()))?;
+			}
+		}
+
+		println!("{} file(s) would be written", self.outputs.len());
+
+		Ok(())
+	}
+
+	/// Combine every source of an output file into a single file, from the lowest to the highest priority.
+	fn resolve(&self, path: &Path, sources: &[Source]) -> Result<Option<Asset>> {
+		// Everything before the last non-merging source will be overridden anyway
+		let sources = match sources
+			.iter()
+			.rposition(|source| source.strategy != Strategy::Merge)
+		{
+			Some(last) => {
+				if last > 0 {
+					overwritten(path, &sources[..=last]);
+				}
+				&sources[last..]
+			}
+			None => sources,
+		};
+
+		let mut result: Option<Asset> = None;
+
+		for source in sources {
+			let file = match self.file(source.index) {
+				Some(file) => file,
+				None => continue,
+			};
+
+			result = match (result, source.strategy) {
+				(Some(previous), Strategy::Merge) => {
+					debug!("Merge {} into the previous file", source.index);
+					Some(previous.merge(file)?)
+				}
+				_ => Some(file),
+			};
+		}
+
+		Ok(result)
+	}
+
+	/// Glyphs must be remapped before any font or language file is read
+	fn remap_glyphs(&self) {
+		let fonts: Vec<FontGlyphs> = self
+			.outputs
+			.iter()
+			.filter(|(path, _)| workspace::font_folder(path))
+			.map(|(path, sources)| {
+				let packs = sources
+					.iter()
+					.filter_map(|source| match self.file(source.index)? {
+						Asset::Font(font) => {
+							Some((*source.index.pid(), font.codepoints().collect()))
+						}
+						_ => None,
+					})
+					.collect();
+				(path.to_owned(), packs)
+			})
+			.collect();
+
+		font::remap_glyphs(&fonts);
+	}
+
+	/// Every CIT rule is kept, but only one of the rules matching the same items will show up in game
+	fn cit_conflicts(&self) {
+		let config = config::get();
+		let mut rules: BTreeMap<String, Vec<(Pid, &Path)>> = BTreeMap::new();
+
+		for (path, sources) in &self.outputs {
+			if !workspace::cit_properties(path) {
+				continue;
+			}
+
+			let source = match sources.last() {
+				Some(source) => source,
+				None => continue,
+			};
+			let rule = match self.file(source.index) {
+				Some(Asset::Cit(cit)) => cit.rule(),
+				_ => None,
+			};
+
+			if let Some(rule) = rule {
+				rules
+					.entry(rule)
+					.or_default()
+					.push((*source.index.pid(), path));
+			}
+		}
+
+		for (rule, mut paths) in rules {
+			paths.sort_by_key(|&(pid, path)| (pid.value(), path));
+
+			let (first, previous) = paths[0];
+			for &(pid, path) in paths.iter().filter(|&&(pid, _)| pid != first) {
+				warn!(
+					"CIT rule {} of {} match the same items as {}",
+					path.display(),
+					config.pack_name(pid),
+					previous.display()
+				);
+				report::record(Entry::CitRule {
+					rule: rule.clone(),
+					previous: previous.to_owned(),
+					path: path.to_owned(),
+					pack: config.pack_name(pid),
+				});
+			}
+		}
+	}
+
+	fn file(&self, index: &Index) -> Option<Asset> {
+		let pid = index.pid();
+		let root = self.projects.get(pid)?;
+		let path = index.prefix(root);
+		let file = Workspace::file(&path, *pid)?;
+		Some(self.mapping.apply_mapping(file))
+	}
+}
+
+fn overwritten(path: &Path, sources: &[Source]) {
+	let config = config::get();
+	let mut names: Vec<String> = sources
+		.iter()
+		.map(|source| config.pack_name(*source.index.pid()))
+		.collect();
+
+	if let Some(winner) = names.pop() {
+		debug!(
+			"{} overwrite {} in {}",
+			winner,
+			names.join(", "),
+			path.display()
+		);
+		let path = path.to_owned();
+
+		// Core shaders replace each other entirely, so the packs depending on the lost ones break
+		if workspace::core_shader(&path) {
+			warn!(
+				"Core shader {} of {} is replaced by {}",
+				path.display(),
+				names.join(", "),
+				winner
+			);
+			report::record(Entry::CoreShader {
+				path,
+				winner,
+				overwritten: names,
+			});
+			return;
+		}
+
+		report::record(Entry::Overwritten {
+			path,
+			winner,
+			overwritten: names,
+		});
+	}
+}
+
+fn describe(path: &Path, sources: &[Source], config: &Config) -> String {
+	let name = |source: &Source| config.pack_name(*source.index.pid());
+	let names = |sources: &[Source]| sources.iter().map(name).collect::<Vec<_>>().join(", ");
+
+	match sources {
+		[source] if source.strategy == Strategy::Rename => format!(
+			"rename  {} -> {} ({})",
+			source.index.path().display(),
+			path.display(),
+			name(source)
+		),
+		[source] => format!("write   {} ({})", path.display(), name(source)),
+		[.., last] if last.strategy == Strategy::Merge => {
+			format!("merge   {} ({})", path.display(), names(sources))
+		}
+		[rest @ .., last] => format!(
+			"replace {} ({} over {})",
+			path.display(),
+			name(last),
+			names(rest)
+		),
+		[] => format!("skip    {}", path.display()),
+	}
+}
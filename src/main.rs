@@ -1,30 +1,37 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use log::*;
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::time::Instant;
 use structopt::StructOpt;
-use superfusion::prelude::Workspace as _;
+use superfusion::prelude::{Project as _, Workspace as _};
 use tempfile::tempdir;
 use zip::ZipWriter;
 use zip_extensions::ZipWriterExtensions;
 
 mod asset;
+mod config;
 mod error;
+mod export;
 mod namespace;
+mod output;
+mod report;
 mod resourcepack;
 mod workspace;
 
-use asset::Asset;
+use asset::{Asset, LangRule};
+use config::Config;
 use error::Error;
-use resourcepack::Resourcepack;
+use export::Export;
+use resourcepack::{Resourcepack, Source};
 use workspace::Workspace;
 
 fn main() {
 	env_logger::init();
 
 	if let Err(e) = run() {
-		println!("{}", e);
+		eprintln!("{:?}", e);
+		std::process::exit(1);
 	}
 }
 
@@ -35,43 +42,120 @@ fn run() -> Result<()> {
 
 	let time = Instant::now();
 
+	let order = match &opt.order_file {
+		Some(path) => read_order(path)?,
+		None => opt.order.clone(),
+	};
+
+	if opt.dry_run {
+		let workspace = workspace(&opt, &order)?;
+		let timeline = workspace.resolve();
+		Export::new(&timeline)?.dry_run()?;
+		return write_report(&opt);
+	}
+
 	let output = if opt.zip {
 		let tempdir = tempdir()?;
 		let output_dir = tempdir.path();
 		debug!("Create temporary directory at {}", output_dir.display());
 
-		merger(&opt.input, output_dir)?;
-
 		let output = opt.output.with_extension("zip");
+		output::prepare_archive(&output, &opt.input, opt.force)?;
+		output::mark(output_dir)?;
+
+		merger(&opt, output_dir, &order)?;
 		zip_dir(&output, output_dir)?;
 
 		output
 	} else {
-		if opt.output.exists() {
-			info!("Cleaning output directory...");
-			std::fs::remove_dir_all(&opt.output)?;
-		}
+		output::prepare(&opt.output, &opt.input, opt.force)?;
+		output::mark(&opt.output)?;
 
-		merger(&opt.input, &opt.output)?;
+		merger(&opt, &opt.output, &order)?;
 
-		opt.output
+		opt.output.clone()
 	};
 
 	let elapsed = time.elapsed();
 	println!("Finished merging resourcepacks in {:.3?}...", elapsed);
 	println!("Output the result into '{}'", output.display());
 
+	write_report(&opt)
+}
+
+fn write_report(opt: &Opt) -> Result<()> {
+	let entries = report::entries();
+
+	if let Some(path) = &opt.report {
+		report::write_json(path, &entries)?;
+		println!("Write merge report into '{}'", path.display());
+	}
+
+	if let Some(path) = &opt.summary {
+		report::write_summary(path, &entries)?;
+		println!("Write merge summary into '{}'", path.display());
+	}
+
+	if opt.report.is_none() && opt.summary.is_none() && !entries.is_empty() {
+		println!(
+			"Recorded {} renamed, overwritten or conflicting entries, use `--report` or `--summary` to see the details",
+			entries.len()
+		);
+	}
+
 	Ok(())
 }
 
-fn merger(input: &Path, output: &Path) -> Result<()> {
-	let workspace = Workspace::from_path(input)?;
+fn merger(opt: &Opt, output: &Path, order: &[String]) -> Result<()> {
+	let workspace = workspace(opt, order)?;
 	let timeline = workspace.resolve();
-	timeline.export_to(output)?;
+	let export = Export::new(&timeline)?;
+	export.export_to(output)?;
 
 	Ok(())
 }
 
+fn workspace(opt: &Opt, order: &[String]) -> Result<Workspace> {
+	let workspace = Workspace::from_path(&opt.input, order)?;
+
+	println!("Merging resourcepacks from the lowest to the highest priority:");
+	for project in workspace.projects() {
+		println!("  {} {}", project.pid(), project.name());
+	}
+
+	config::set(Config {
+		pack_names: workspace
+			.projects()
+			.iter()
+			.map(|project| project.name().to_string())
+			.collect(),
+		pack_format: opt.pack_format,
+		remap_custom_model_data: opt.remap_custom_model_data,
+		remap_glyphs: opt.remap_glyphs,
+		convert_lang: opt.convert_lang,
+		lang_policies: opt.lang_policy.clone(),
+		pretty: opt.pretty,
+	});
+
+	Ok(workspace)
+}
+
+/// Read pack order file, each line contain a single pack name.
+///
+/// Empty lines and lines starting with `#` are ignored.
+fn read_order(path: &Path) -> Result<Vec<String>> {
+	let content = std::fs::read_to_string(path)
+		.with_context(|| format!("Failed to read pack order file at {}", path.display()))?;
+
+	let result = content
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.map(String::from)
+		.collect();
+	Ok(result)
+}
+
 fn zip_dir(path: &Path, from: &Path) -> Result<()> {
 	let file = File::create(path)?;
 	let mut zipper = ZipWriter::new(file);
@@ -82,7 +166,7 @@ fn zip_dir(path: &Path, from: &Path) -> Result<()> {
 #[derive(Debug, StructOpt)]
 #[structopt(about = "Catalyst for merging resourcepack")]
 pub struct Opt {
-	/// Input directory
+	/// Input directory containing resourcepack folders and/or zip files
 	#[structopt(parse(from_str))]
 	input: PathBuf,
 
@@ -93,4 +177,54 @@ pub struct Opt {
 	/// Compress the output directory into zip file
 	#[structopt(long, short)]
 	zip: bool,
+
+	/// Clear the output directory or overwrite the archive even if it was not created by ribozyme
+	#[structopt(long, short)]
+	force: bool,
+
+	/// Print what would be written, renamed, merged or replaced without touching the output
+	#[structopt(long)]
+	dry_run: bool,
+
+	/// Comma-separated pack names from the lowest to the highest priority, unlisted packs are sorted alphabetically before them
+	#[structopt(long, use_delimiter = true)]
+	order: Vec<String>,
+
+	/// File containing one pack name per line, works the same as `--order`
+	#[structopt(long, parse(from_os_str), conflicts_with = "order")]
+	order_file: Option<PathBuf>,
+
+	/// Use this `pack_format` in the merged pack.mcmeta instead of the highest one
+	#[structopt(long)]
+	pack_format: Option<u32>,
+
+	/// Move colliding CustomModelData of later packs to unused values, the mapping is listed in the report
+	#[structopt(long)]
+	remap_custom_model_data: bool,
+
+	/// Move colliding font glyphs of later packs to unused Private Use Area codepoints and update language files.
+	/// The mapping is listed in the report
+	#[structopt(long)]
+	remap_glyphs: bool,
+
+	/// Convert language files between `.lang` and `.json` to match `--pack-format`
+	#[structopt(long, requires = "pack-format")]
+	convert_lang: bool,
+
+	/// How conflicting language keys are resolved: `[scope=]first-wins|last-wins|fail|keep-both`.
+	/// Scope is either a namespace (`boomber:`) or a key prefix (`item.boomber.`), can be given multiple times
+	#[structopt(long, number_of_values = 1)]
+	lang_policy: Vec<LangRule>,
+
+	/// Pretty-print JSON files for review instead of minifying them
+	#[structopt(long)]
+	pretty: bool,
+
+	/// Write every overwritten, renamed and conflicting entry into this JSON file
+	#[structopt(long, parse(from_os_str))]
+	report: Option<PathBuf>,
+
+	/// Write a human-readable Markdown summary of the merge report into this file
+	#[structopt(long, parse(from_os_str))]
+	summary: Option<PathBuf>,
 }
@@ -10,11 +10,14 @@ mod serialization;
 
 #[derive(Debug, PartialEq)]
 pub enum Kind {
+	#[allow(dead_code)]
 	BlockState,
 	Model,
 	Texture,
+	#[allow(dead_code)]
 	Lang,
 	Font,
+	Sound,
 }
 
 impl Kind {
@@ -22,6 +25,7 @@ impl Kind {
 		match self {
 			Self::BlockState | Self::Model | Self::Lang | Self::Font => "json",
 			Self::Texture => "png",
+			Self::Sound => "ogg",
 		}
 	}
 
@@ -32,6 +36,7 @@ impl Kind {
 			Self::Texture => "textures",
 			Self::Lang => "lang",
 			Self::Font => "font",
+			Self::Sound => "sounds",
 		}
 	}
 }
@@ -111,7 +116,7 @@ fn advance<'a, I>(iter: &mut I) -> Result<Component<'a>, NamespaceError>
 where
 	I: Iterator<Item = Component<'a>>,
 {
-	iter.next().ok_or_else(|| NamespaceError::PathTooShort)
+	iter.next().ok_or(NamespaceError::PathTooShort)
 }
 
 impl fmt::Display for Namespace {
@@ -145,7 +150,7 @@ pub enum NamespaceError {
 
 impl PartialOrd for Namespace {
 	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-		self.raw().partial_cmp(&other.raw())
+		Some(self.cmp(other))
 	}
 }
 
@@ -0,0 +1,191 @@
+use super::Error;
+use anyhow::{Context, Result};
+use log::*;
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// File that mark a directory as ribozyme's output, it's safe to clear directories containing this file.
+pub const MARKER: &str = ".ribozyme";
+
+/// Make sure that the output directory can be written to, clearing the previous output if necessary.
+///
+/// This refuse to touch directory that is not created by ribozyme unless `force` is set.
+pub fn prepare(output: &Path, input: &Path, force: bool) -> Result<()> {
+	check_overlap(output, input)?;
+
+	if !output.exists() {
+		return Ok(());
+	}
+
+	if output.is_file() {
+		if !force {
+			return Err(Error::UnsafeOutput(output.to_owned()).into());
+		}
+
+		std::fs::remove_file(output)?;
+		return Ok(());
+	}
+
+	if is_empty(output)? {
+		return Ok(());
+	}
+
+	if !force && !output.join(MARKER).is_file() {
+		return Err(Error::UnsafeOutput(output.to_owned()).into());
+	}
+
+	info!("Cleaning output directory...");
+	std::fs::remove_dir_all(output)
+		.with_context(|| format!("Failed to clear output directory {}", output.display()))?;
+
+	Ok(())
+}
+
+/// Same as [prepare()](fn.prepare.html) for the `--zip` output, an archive is ribozyme's output when it contains the marker.
+///
+/// The archive is left in place since it is truncated when the new one is written.
+pub fn prepare_archive(output: &Path, input: &Path, force: bool) -> Result<()> {
+	check_overlap(output, input)?;
+
+	if !output.exists() || force || is_marked_archive(output) {
+		return Ok(());
+	}
+
+	Err(Error::UnsafeOutput(output.to_owned()).into())
+}
+
+fn is_marked_archive(path: &Path) -> bool {
+	let archive = std::fs::File::open(path)
+		.ok()
+		.and_then(|file| ZipArchive::new(file).ok());
+
+	match archive {
+		Some(mut archive) => archive.by_name(MARKER).is_ok(),
+		None => false,
+	}
+}
+
+/// Mark the given directory as ribozyme's output, creating it if necessary.
+///
+/// This is done before exporting so that a failed merge can still be cleared on the next run.
+pub fn mark(output: &Path) -> Result<()> {
+	std::fs::create_dir_all(output)
+		.with_context(|| format!("Creating output directory {}", output.display()))?;
+
+	let path = output.join(MARKER);
+	std::fs::write(
+		&path,
+		"This directory was generated by ribozyme and will be cleared on the next run.\n",
+	)
+	.with_context(|| format!("Failed to write output marker {}", path.display()))
+}
+
+/// Refuse output path that is inside the input directory or contain the input directory
+pub fn check_overlap(output: &Path, input: &Path) -> Result<()> {
+	let output = normalize(output)?;
+	let input = normalize(input)?;
+
+	if output.starts_with(&input) || input.starts_with(&output) {
+		return Err(Error::OutputOverlap(output, input).into());
+	}
+
+	Ok(())
+}
+
+fn is_empty(path: &Path) -> Result<bool> {
+	let mut entries = path
+		.read_dir()
+		.with_context(|| format!("Failed to read output directory {}", path.display()))?;
+	Ok(entries.next().is_none())
+}
+
+/// Absolute path with symlinks resolved, the path does not need to exist.
+fn normalize(path: &Path) -> Result<PathBuf> {
+	let path = std::env::current_dir()?.join(path);
+
+	let mut existing = path.as_path();
+	let mut rest = vec![];
+
+	while !existing.exists() {
+		match (existing.file_name(), existing.parent()) {
+			(Some(name), Some(parent)) => {
+				rest.push(name);
+				existing = parent;
+			}
+			_ => break,
+		}
+	}
+
+	let mut result = existing
+		.canonicalize()
+		.unwrap_or_else(|_| existing.to_owned());
+	result.extend(rest.into_iter().rev());
+	Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn output_inside_input() {
+		let input = tempfile::tempdir().unwrap();
+		let output = input.path().join("merged");
+		assert!(check_overlap(&output, input.path()).is_err());
+		assert!(check_overlap(input.path(), &output).is_err());
+	}
+
+	#[test]
+	fn output_next_to_input() {
+		let root = tempfile::tempdir().unwrap();
+		let input = root.path().join("packs");
+		let output = root.path().join("packs_merged");
+		assert!(check_overlap(&output, &input).is_ok());
+	}
+
+	#[test]
+	fn refuse_foreign_directory() {
+		let root = tempfile::tempdir().unwrap();
+		let input = root.path().join("packs");
+		let output = root.path().join("work");
+		std::fs::create_dir(&output).unwrap();
+		std::fs::write(output.join("notes.txt"), "important").unwrap();
+
+		assert!(prepare(&output, &input, false).is_err());
+
+		mark(&output).unwrap();
+		assert!(prepare(&output, &input, false).is_ok());
+		assert!(!output.exists());
+	}
+
+	#[test]
+	fn mark_before_export() {
+		let root = tempfile::tempdir().unwrap();
+		let input = root.path().join("packs");
+		let output = root.path().join("merged");
+
+		prepare(&output, &input, false).unwrap();
+		mark(&output).unwrap();
+
+		// A merge that fail at this point leave a directory that the next run can clear
+		std::fs::write(output.join("partial.json"), "{").unwrap();
+		assert!(prepare(&output, &input, false).is_ok());
+	}
+
+	#[test]
+	fn refuse_foreign_archive() {
+		let root = tempfile::tempdir().unwrap();
+		let input = root.path().join("packs");
+		let output = root.path().join("merged.zip");
+		assert!(prepare_archive(&output, &input, false).is_ok());
+
+		std::fs::write(&output, "important").unwrap();
+		assert!(prepare_archive(&output, &input, false).is_err());
+		assert!(prepare_archive(&output, &input, true).is_ok());
+
+		let mut zip = zip::ZipWriter::new(std::fs::File::create(&output).unwrap());
+		zip.start_file(MARKER, Default::default()).unwrap();
+		zip.finish().unwrap();
+		assert!(prepare_archive(&output, &input, false).is_ok());
+	}
+}
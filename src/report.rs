@@ -0,0 +1,339 @@
+use crate::asset::LangPolicy;
+use anyhow::{Context, Result};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+/// A single thing that the merge did which reviewers might want to know about.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Entry {
+	/// A file was replaced entirely by a higher priority pack
+	Overwritten {
+		path: PathBuf,
+		winner: String,
+		overwritten: Vec<String>,
+	},
+	/// A file was renamed to avoid conflict
+	Renamed {
+		pack: String,
+		from: PathBuf,
+		to: PathBuf,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		from_namespace: Option<String>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		to_namespace: Option<String>,
+	},
+	/// The same language key was defined by multiple packs
+	LangKey {
+		path: PathBuf,
+		key: String,
+		previous: String,
+		value: String,
+		policy: LangPolicy,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		renamed: Option<String>,
+	},
+	/// Multiple packs define a model override with the same predicate
+	OverridePredicate {
+		path: PathBuf,
+		predicate: String,
+		previous: String,
+		model: String,
+	},
+	/// CustomModelData of a pack was moved to an unused value
+	CustomModelData {
+		path: PathBuf,
+		pack: String,
+		from: usize,
+		to: usize,
+	},
+	/// The same blockstate variant was defined differently by multiple packs
+	BlockStateVariant { path: PathBuf, variant: String },
+	/// Blockstate files using different formats could not be merged
+	BlockStateFormat { path: PathBuf },
+	/// A sound event was replaced instead of extended by a higher priority pack
+	SoundEvent { path: PathBuf, event: String },
+	/// The same bitmap glyph was defined by multiple packs
+	FontGlyph {
+		path: PathBuf,
+		codepoint: String,
+		previous: String,
+		pack: String,
+	},
+	/// Font glyph of a pack was moved to an unused codepoint
+	FontGlyphRemapped {
+		path: PathBuf,
+		pack: String,
+		from: String,
+		to: String,
+	},
+	/// Particle sprites were replaced by a higher priority pack
+	ParticleSprites {
+		path: PathBuf,
+		previous: Vec<String>,
+		textures: Vec<String>,
+	},
+	/// CIT rules of different packs match the same items
+	CitRule {
+		rule: String,
+		previous: PathBuf,
+		path: PathBuf,
+		pack: String,
+	},
+	/// A core shader was replaced by a higher priority pack, core shaders cannot be merged
+	CoreShader {
+		path: PathBuf,
+		winner: String,
+		overwritten: Vec<String>,
+	},
+}
+
+impl Entry {
+	fn title(&self) -> &str {
+		match self {
+			Self::Overwritten { .. } => "Overwritten files",
+			Self::Renamed { .. } => "Renamed files",
+			Self::LangKey { .. } => "Language key conflicts",
+			Self::OverridePredicate { .. } => "Model override conflicts",
+			Self::CustomModelData { .. } => "Remapped CustomModelData",
+			Self::BlockStateVariant { .. } => "Blockstate variant conflicts",
+			Self::BlockStateFormat { .. } => "Blockstate format conflicts",
+			Self::SoundEvent { .. } => "Replaced sound events",
+			Self::FontGlyph { .. } => "Font glyph conflicts",
+			Self::FontGlyphRemapped { .. } => "Remapped font glyphs",
+			Self::ParticleSprites { .. } => "Particle sprite conflicts",
+			Self::CitRule { .. } => "CIT rule conflicts",
+			Self::CoreShader { .. } => "Core shader conflicts",
+		}
+	}
+
+	fn describe(&self) -> String {
+		match self {
+			Self::Overwritten {
+				path,
+				winner,
+				overwritten,
+			} => format!(
+				"`{}`: {} overwrite {}",
+				path.display(),
+				winner,
+				overwritten.join(", ")
+			),
+			Self::Renamed {
+				pack,
+				from,
+				to,
+				from_namespace,
+				to_namespace,
+			} => match (from_namespace, to_namespace) {
+				(Some(from), Some(to)) => format!("`{}` → `{}` ({})", from, to, pack),
+				_ => format!("`{}` → `{}` ({})", from.display(), to.display(), pack),
+			},
+			Self::LangKey {
+				path,
+				key,
+				previous,
+				value,
+				policy,
+				renamed,
+			} => match (policy, renamed) {
+				(LangPolicy::FirstWins, _) | (LangPolicy::Fail, _) => format!(
+					"`{}`: `{}` kept {:?} over {:?}",
+					path.display(),
+					key,
+					previous,
+					value
+				),
+				(LangPolicy::KeepBoth, Some(renamed)) => format!(
+					"`{}`: `{}` kept {:?}, {:?} moved to `{}`",
+					path.display(),
+					key,
+					previous,
+					value,
+					renamed
+				),
+				_ => format!(
+					"`{}`: `{}` {:?} replaced with {:?}",
+					path.display(),
+					key,
+					previous,
+					value
+				),
+			},
+			Self::OverridePredicate {
+				path,
+				predicate,
+				previous,
+				model,
+			} => format!(
+				"`{}`: `{}` is used by both `{}` and `{}`",
+				path.display(),
+				predicate,
+				previous,
+				model
+			),
+			Self::CustomModelData {
+				path,
+				pack,
+				from,
+				to,
+			} => format!("`{}`: {} → {} ({})", path.display(), from, to, pack),
+			Self::BlockStateVariant { path, variant } => {
+				format!("`{}`: variant `{}`", path.display(), variant)
+			}
+			Self::BlockStateFormat { path } => format!(
+				"`{}`: 'variants' and 'multipart' cannot be merged",
+				path.display()
+			),
+			Self::SoundEvent { path, event } => {
+				format!("`{}`: `{}` replaced", path.display(), event)
+			}
+			Self::FontGlyph {
+				path,
+				codepoint,
+				previous,
+				pack,
+			} => format!(
+				"`{}`: {} is defined by both {} and {}",
+				path.display(),
+				codepoint,
+				previous,
+				pack
+			),
+			Self::FontGlyphRemapped {
+				path,
+				pack,
+				from,
+				to,
+			} => format!("`{}`: {} → {} ({})", path.display(), from, to, pack),
+			Self::ParticleSprites {
+				path,
+				previous,
+				textures,
+			} => format!(
+				"`{}`: [{}] replaced with [{}]",
+				path.display(),
+				previous.join(", "),
+				textures.join(", ")
+			),
+			Self::CitRule {
+				rule,
+				previous,
+				path,
+				pack,
+			} => format!(
+				"`{}` ({}) match the same items as `{}`: {}",
+				path.display(),
+				pack,
+				previous.display(),
+				rule
+			),
+			Self::CoreShader {
+				path,
+				winner,
+				overwritten,
+			} => format!(
+				"`{}`: {} overwrite {}, core shaders cannot be merged",
+				path.display(),
+				winner,
+				overwritten.join(", ")
+			),
+		}
+	}
+}
+
+lazy_static! {
+	static ref REPORT: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+}
+
+pub fn record(entry: Entry) {
+	if let Ok(mut report) = REPORT.lock() {
+		report.push(entry);
+	}
+}
+
+pub fn entries() -> Vec<Entry> {
+	REPORT
+		.lock()
+		.map(|report| report.clone())
+		.unwrap_or_default()
+}
+
+/// Strip everything before the `assets` directory so the path is relative to the resourcepack
+pub fn asset_path(path: &Path) -> PathBuf {
+	let components: Vec<Component> = path.components().collect();
+	match components.iter().rposition(|c| c.as_os_str() == "assets") {
+		Some(position) => components[position..].iter().collect(),
+		None => path.file_name().map(PathBuf::from).unwrap_or_default(),
+	}
+}
+
+pub fn write_json(path: &Path, entries: &[Entry]) -> Result<()> {
+	let content = serde_json::to_vec_pretty(entries)?;
+	std::fs::write(path, content)
+		.with_context(|| format!("Failed to write report to {}", path.display()))
+}
+
+pub fn write_summary(path: &Path, entries: &[Entry]) -> Result<()> {
+	std::fs::write(path, summary(entries))
+		.with_context(|| format!("Failed to write summary to {}", path.display()))
+}
+
+/// Markdown summary of the report, grouped by the kind of entry
+pub fn summary(entries: &[Entry]) -> String {
+	let mut result = String::from("# Merge Report\n");
+
+	if entries.is_empty() {
+		result.push_str("\nNothing to report.\n");
+		return result;
+	}
+
+	let mut titles: Vec<&str> = vec![];
+	for entry in entries {
+		if !titles.contains(&entry.title()) {
+			titles.push(entry.title());
+		}
+	}
+
+	for title in titles {
+		let _ = writeln!(result, "\n## {}\n", title);
+		for entry in entries.iter().filter(|entry| entry.title() == title) {
+			let _ = writeln!(result, "- {}", entry.describe());
+		}
+	}
+
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn strip_asset_path() {
+		let result = asset_path(Path::new("/tmp/pack/assets/minecraft/lang/en_us.json"));
+		assert_eq!(result, PathBuf::from("assets/minecraft/lang/en_us.json"));
+	}
+
+	#[test]
+	fn summary_grouping() {
+		let entries = vec![
+			Entry::BlockStateFormat {
+				path: PathBuf::from("a.json"),
+			},
+			Entry::BlockStateVariant {
+				path: PathBuf::from("b.json"),
+				variant: "facing=north".to_string(),
+			},
+			Entry::BlockStateFormat {
+				path: PathBuf::from("c.json"),
+			},
+		];
+		let result = summary(&entries);
+		assert_eq!(result.matches("## ").count(), 2);
+		assert!(result.contains("- `c.json`"));
+	}
+}
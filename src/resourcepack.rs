@@ -1,34 +1,122 @@
+use anyhow::{Context, Result};
 use log::*;
 use std::{
 	collections::HashSet,
 	path::{Path, PathBuf},
 };
 use superfusion::prelude::{Index, IndexList, Pid, Project};
+use tempfile::TempDir;
 use walkdir::WalkDir;
+use zip::ZipArchive;
+
+/// Location of a resourcepack's content on the filesystem.
+///
+/// Zipped resourcepacks are extracted into a temporary directory that live as long as this struct.
+pub struct Source {
+	name: String,
+	root: PathBuf,
+	_extracted: Option<TempDir>,
+}
+
+impl Source {
+	pub fn directory<P: Into<PathBuf>>(root: P) -> Self {
+		let root = root.into();
+		Self {
+			name: file_name(&root),
+			root,
+			_extracted: None,
+		}
+	}
+
+	pub fn archive<P: AsRef<Path>>(path: P) -> Result<Self> {
+		let path = path.as_ref();
+
+		info!("Extracting resourcepack from archive: {}", path.display());
+
+		let file = std::fs::File::open(path).with_context(|| "Reading archive file")?;
+		let mut archive = ZipArchive::new(file).with_context(|| "Parsing archive file")?;
+
+		let extracted = tempfile::tempdir()?;
+		archive
+			.extract(extracted.path())
+			.with_context(|| "Extracting archive file")?;
+
+		debug!(
+			"Extracted {} entries into {}",
+			archive.len(),
+			extracted.path().display()
+		);
+
+		let root =
+			wrapper_directory(extracted.path()).unwrap_or_else(|| extracted.path().to_owned());
+
+		let result = Self {
+			name: file_name(path),
+			root,
+			_extracted: Some(extracted),
+		};
+		Ok(result)
+	}
+
+	/// Name of the resourcepack as it appear in the input directory
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn root(&self) -> &Path {
+		&self.root
+	}
+}
+
+fn file_name(path: &Path) -> String {
+	path.file_name()
+		.map(|name| name.to_string_lossy().to_string())
+		.unwrap_or_default()
+}
+
+/// Some archives wrap the whole resourcepack inside a single top-level folder, return that folder if it's the case.
+fn wrapper_directory(path: &Path) -> Option<PathBuf> {
+	let mut entries = path.read_dir().ok()?.filter_map(|entry| entry.ok());
+	let entry = entries.next()?;
+
+	if entries.next().is_some() || !entry.path().is_dir() {
+		return None;
+	}
+
+	Some(entry.path())
+}
 
 pub struct Resourcepack {
 	indexes: HashSet<Index>,
-	root: PathBuf,
+	source: Source,
 	pid: Pid,
 }
 
 impl Resourcepack {
-	pub fn from_path<P: Into<PathBuf>>(root: P, pid: Pid) -> Self {
-		let root = root.into();
+	pub fn from_source(source: Source, pid: Pid) -> Self {
+		let root = source.root();
 
 		info!("Initializing resourcepack from path: {}", root.display());
 
-		let indexes = WalkDir::new(&root)
+		let indexes = WalkDir::new(root)
 			.into_iter()
 			.filter_map(resourcepack_entry)
 			.filter(|p| p.is_file())
-			.filter_map(|path| path.strip_prefix(&root).map(|p| p.to_owned()).ok())
+			.filter_map(|path| path.strip_prefix(root).map(|p| p.to_owned()).ok())
 			.map(|path| Index::new(pid, path))
 			.collect::<HashSet<_>>();
 
 		debug!("Found {} files from this resourcepack", indexes.len());
 
-		Self { indexes, root, pid }
+		Self {
+			indexes,
+			source,
+			pid,
+		}
+	}
+
+	pub fn name(&self) -> &str {
+		self.source.name()
 	}
 }
 
@@ -40,12 +128,12 @@ fn resourcepack_entry(entry: walkdir::Result<walkdir::DirEntry>) -> Option<PathB
 
 impl Project for Resourcepack {
 	fn root(&self) -> &Path {
-		&self.root
+		self.source.root()
 	}
 	fn pid(&self) -> Pid {
 		self.pid
 	}
-	fn indexes(&self) -> IndexList {
+	fn indexes(&self) -> IndexList<'_> {
 		self.indexes.iter().collect()
 	}
 }
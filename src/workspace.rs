@@ -1,5 +1,5 @@
-use super::{Asset, Resourcepack};
-use anyhow::Result;
+use super::{Asset, Error, Resourcepack, Source};
+use anyhow::{Context, Result};
 use glob::Pattern;
 use lazy_static::lazy_static;
 use log::*;
@@ -22,17 +22,25 @@ pub struct Workspace {
 }
 
 impl Workspace {
-	pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+	/// Create workspace from every resourcepack inside the given directory.
+	///
+	/// `order` list the resourcepack's name from the lowest to the highest priority, see [prioritize()](fn.prioritize.html).
+	pub fn from_path<P: AsRef<Path>>(path: P, order: &[String]) -> Result<Self> {
 		let composite = criteria();
 
 		let path = path.as_ref();
 
 		info!("Initialize Workspace from path: {}", path.display());
 
-		let projects = path
+		let sources = path
 			.read_dir()?
 			.filter_map(path_entry)
-			.filter(|path| composite.check(path))
+			.filter_map(source)
+			.filter(|source| composite.check(source.root()))
+			.collect::<Vec<_>>();
+
+		let projects = prioritize(sources, order)?
+			.into_iter()
 			.enumerate()
 			.map(resourcepack)
 			.collect::<Vec<_>>();
@@ -50,9 +58,58 @@ fn path_entry(entry: io::Result<DirEntry>) -> Option<PathBuf> {
 	Some(path)
 }
 
-fn resourcepack((n, path): (usize, PathBuf)) -> Resourcepack {
+fn source(path: PathBuf) -> Option<Source> {
+	if path.is_dir() {
+		return Some(Source::directory(path));
+	}
+
+	if !archive(&path) {
+		return None;
+	}
+
+	Source::archive(&path)
+		.with_context(|| format!("Failed to read archive at {}", path.display()))
+		.map_err(|err| error!("\n{:?}", err))
+		.ok()
+}
+
+/// Sort sources from the lowest to the highest priority.
+///
+/// Sources are sorted lexicographically by name by default,
+/// sources listed in `order` are moved after the rest in the given order so that they take precedence.
+fn prioritize(mut sources: Vec<Source>, order: &[String]) -> Result<Vec<Source>> {
+	sources.sort_by(|a, b| a.name().cmp(b.name()));
+
+	let mut ordered = Vec::with_capacity(order.len());
+
+	for name in order {
+		if ordered.iter().any(|source: &Source| source.name() == name) {
+			return Err(Error::DuplicatePack(name.clone()).into());
+		}
+
+		let position = sources
+			.iter()
+			.position(|source| source.name() == name)
+			.ok_or_else(|| Error::UnknownPack(name.clone()))?;
+		ordered.push(sources.remove(position));
+	}
+
+	if !ordered.is_empty() {
+		for source in &sources {
+			warn!(
+				"'{}' is not listed in the pack order, it will have the lowest priority",
+				source.name()
+			);
+		}
+	}
+
+	sources.append(&mut ordered);
+	Ok(sources)
+}
+
+fn resourcepack((n, source): (usize, Source)) -> Resourcepack {
 	let pid = Pid::new(n);
-	Resourcepack::from_path(path, pid)
+	Resourcepack::from_source(source, pid)
 }
 
 impl superfusion::prelude::Workspace for Workspace {
@@ -69,16 +126,32 @@ impl superfusion::prelude::Workspace for Workspace {
 		let is_lang = lang_folder(path);
 		let is_texture = texture_folder(path);
 		let is_pack_meta = pack_meta(path);
+		let is_blockstate = blockstate_folder(path);
+		let is_sounds = sounds(path);
+		let is_font = font_folder(path);
+		let is_atlas = atlas_folder(path);
+		let is_sound_file = sound_file(path);
+		let is_shader = shader_program(path) || shader_source(path);
+		let is_post_effect = post_effect(path);
+		let is_particle = particle_folder(path);
+		let is_text = text_file(path);
+		let is_cit = cit_folder(path);
+
+		// CIT rules match items by themselves, so every rule and its files are kept
+		if is_cit {
+			return Strategy::Rename;
+		}
 
 		if is_vanilla && is_models {
 			return Strategy::Merge;
 		}
 
-		if !is_vanilla && (is_models || is_texture) {
+		if !is_vanilla && (is_models || is_texture || is_sound_file || is_shader) {
 			return Strategy::Rename;
 		}
 
-		if is_lang || is_pack_meta {
+		let is_mergeable = is_lang || is_pack_meta || is_blockstate || is_sounds || is_font;
+		if is_mergeable || is_atlas || is_post_effect || is_particle || is_text {
 			return Strategy::Merge;
 		}
 
@@ -95,8 +168,25 @@ lazy_static! {
 	static ref MINECRAFT_FOLDER: Pattern = Pattern::new("**/assets/minecraft/**").unwrap();
 	static ref MODEL: Pattern = Pattern::new("**/assets/*/models/**/*.json").unwrap();
 	static ref LANG: Pattern = Pattern::new("**/assets/*/lang/**/*.json").unwrap();
+	static ref LEGACY_LANG: Pattern = Pattern::new("**/assets/*/lang/**/*.lang").unwrap();
 	static ref TEXTURE: Pattern = Pattern::new("**/assets/*/textures/**/*.png").unwrap();
+	static ref TEXTURE_META: Pattern =
+		Pattern::new("**/assets/*/textures/**/*.png.mcmeta").unwrap();
 	static ref PACK_META: Pattern = Pattern::new("**/pack.mcmeta").unwrap();
+	static ref ATLAS: Pattern = Pattern::new("**/assets/*/atlases/**/*.json").unwrap();
+	static ref FONT: Pattern = Pattern::new("**/assets/*/font/**/*.json").unwrap();
+	static ref SOUNDS: Pattern = Pattern::new("**/assets/*/sounds.json").unwrap();
+	static ref SOUND_FILE: Pattern = Pattern::new("**/assets/*/sounds/**/*.ogg").unwrap();
+	static ref SHADER: Pattern = Pattern::new("**/assets/*/shaders/**").unwrap();
+	static ref SHADER_PROGRAM: Pattern = Pattern::new("**/assets/*/shaders/*/**/*.json").unwrap();
+	static ref CORE_SHADER: Pattern = Pattern::new("**/assets/minecraft/shaders/core/**").unwrap();
+	static ref POST_EFFECT: Pattern = Pattern::new("**/assets/*/shaders/post/**/*.json").unwrap();
+	static ref NEW_POST_EFFECT: Pattern =
+		Pattern::new("**/assets/*/post_effect/**/*.json").unwrap();
+	static ref PARTICLE: Pattern = Pattern::new("**/assets/*/particles/**/*.json").unwrap();
+	static ref TEXT: Pattern = Pattern::new("**/assets/*/texts/**/*.txt").unwrap();
+	static ref CIT: Pattern = Pattern::new("**/assets/minecraft/optifine/cit/**").unwrap();
+	static ref LEGACY_CIT: Pattern = Pattern::new("**/assets/minecraft/mcpatcher/cit/**").unwrap();
 	static ref BLOCKSTATE: Pattern = Pattern::new("**/assets/*/blockstates/**/*.json").unwrap();
 }
 
@@ -109,17 +199,170 @@ pub fn models_folder(path: &Path) -> bool {
 }
 
 pub fn lang_folder(path: &Path) -> bool {
-	LANG.matches_path(path)
+	LANG.matches_path(path) || legacy_lang_folder(path)
+}
+
+pub fn legacy_lang_folder(path: &Path) -> bool {
+	LEGACY_LANG.matches_path(path)
 }
 
 pub fn texture_folder(path: &Path) -> bool {
 	TEXTURE.matches_path(path)
 }
 
+pub fn texture_meta(path: &Path) -> bool {
+	TEXTURE_META.matches_path(path)
+}
+
 pub fn pack_meta(path: &Path) -> bool {
 	PACK_META.matches_path(path)
 }
 
+pub fn atlas_folder(path: &Path) -> bool {
+	ATLAS.matches_path(path)
+}
+
+pub fn font_folder(path: &Path) -> bool {
+	FONT.matches_path(path)
+}
+
+pub fn sounds(path: &Path) -> bool {
+	SOUNDS.matches_path(path)
+}
+
+pub fn sound_file(path: &Path) -> bool {
+	SOUND_FILE.matches_path(path)
+}
+
+/// Program JSON in `shaders/core`, `shaders/program` or any other shader folder except `shaders/post`
+pub fn shader_program(path: &Path) -> bool {
+	SHADER_PROGRAM.matches_path(path) && !post_effect(path)
+}
+
+/// `.vsh`, `.fsh` or `.glsl` file anywhere in `shaders`
+pub fn shader_source(path: &Path) -> bool {
+	let is_source = path
+		.extension()
+		.is_some_and(|ext| ext == "vsh" || ext == "fsh" || ext == "glsl");
+	is_source && SHADER.matches_path(path)
+}
+
+pub fn core_shader(path: &Path) -> bool {
+	CORE_SHADER.matches_path(path)
+}
+
+pub fn post_effect(path: &Path) -> bool {
+	POST_EFFECT.matches_path(path) || NEW_POST_EFFECT.matches_path(path)
+}
+
+pub fn particle_folder(path: &Path) -> bool {
+	PARTICLE.matches_path(path)
+}
+
+/// Line-based text such as `splashes.txt`, `end.txt` or `postcredits.txt`
+pub fn text_file(path: &Path) -> bool {
+	TEXT.matches_path(path)
+}
+
+/// OptiFine CIT folder, `mcpatcher/cit` is the older name of `optifine/cit`
+pub fn cit_folder(path: &Path) -> bool {
+	CIT.matches_path(path) || LEGACY_CIT.matches_path(path)
+}
+
+pub fn cit_properties(path: &Path) -> bool {
+	cit_folder(path) && path.extension().is_some_and(|ext| ext == "properties")
+}
+
 pub fn blockstate_folder(path: &Path) -> bool {
 	BLOCKSTATE.matches_path(path)
 }
+
+pub fn archive(path: &Path) -> bool {
+	path.is_file()
+		&& path
+			.extension()
+			.is_some_and(|ext| ext.eq_ignore_ascii_case("zip"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Write;
+	use superfusion::prelude::Project;
+	use zip::write::{FileOptions, ZipWriter};
+
+	fn sources(names: &[&str]) -> Vec<Source> {
+		names.iter().map(Source::directory).collect()
+	}
+
+	fn names(sources: Vec<Source>) -> Vec<String> {
+		sources.iter().map(|s| s.name().to_string()).collect()
+	}
+
+	#[test]
+	fn prioritize_default() {
+		let result = prioritize(sources(&["charlie", "alpha", "bravo"]), &[]).unwrap();
+		assert_eq!(names(result), vec!["alpha", "bravo", "charlie"]);
+	}
+
+	#[test]
+	fn prioritize_with_order() {
+		let order = vec!["charlie".to_string(), "alpha".to_string()];
+		let result = prioritize(sources(&["alpha", "bravo", "charlie"]), &order).unwrap();
+		assert_eq!(names(result), vec!["bravo", "charlie", "alpha"]);
+	}
+
+	#[test]
+	fn prioritize_unknown_pack() {
+		let order = vec!["delta".to_string()];
+		assert!(prioritize(sources(&["alpha"]), &order).is_err());
+	}
+
+	#[test]
+	fn prioritize_duplicate_pack() {
+		let order = vec!["alpha".to_string(), "alpha".to_string()];
+		assert!(prioritize(sources(&["alpha"]), &order).is_err());
+	}
+
+	/// Zip a minimal resourcepack with every entry under `prefix`
+	fn zipped(path: &Path, prefix: &str) {
+		let file = std::fs::File::create(path).unwrap();
+		let mut zip = ZipWriter::new(file);
+		for name in &["pack.mcmeta", "assets/minecraft/lang/en_us.json"] {
+			zip.start_file(format!("{}{}", prefix, name), FileOptions::default())
+				.unwrap();
+			zip.write_all(b"{}").unwrap();
+		}
+		zip.finish().unwrap();
+	}
+
+	fn zipped_pack(prefix: &str) {
+		let root = tempfile::tempdir().unwrap();
+		let path = root.path().join("boomber.ZIP");
+		zipped(&path, prefix);
+
+		let source = source(path).unwrap();
+		assert_eq!(source.name(), "boomber.ZIP");
+
+		let pack = Resourcepack::from_source(source, Pid::new(0));
+		let mut indexes: Vec<&Path> = pack.indexes().into_iter().map(Index::path).collect();
+		indexes.sort();
+		assert_eq!(
+			indexes,
+			vec![
+				Path::new("assets/minecraft/lang/en_us.json"),
+				Path::new("pack.mcmeta")
+			]
+		);
+	}
+
+	#[test]
+	fn zipped_resourcepack() {
+		zipped_pack("");
+	}
+
+	#[test]
+	fn zipped_resourcepack_with_wrapper_folder() {
+		zipped_pack("boomber/");
+	}
+}
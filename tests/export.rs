@@ -0,0 +1,79 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn pack(root: &Path, name: &str, files: &[(&str, &str)]) {
+	let pack = root.join(name);
+	fs::create_dir_all(&pack).unwrap();
+	fs::write(
+		pack.join("pack.mcmeta"),
+		r#"{"pack":{"pack_format":6,"description":""}}"#,
+	)
+	.unwrap();
+
+	for (path, content) in files {
+		let path = pack.join(path);
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		fs::write(path, content).unwrap();
+	}
+}
+
+fn ribozyme(input: &Path, output: &Path, args: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_ribozyme"))
+		.arg(input)
+		.arg(output)
+		.args(args)
+		.output()
+		.unwrap()
+}
+
+#[test]
+fn merge_failure_exit_without_writing() {
+	let root = tempfile::tempdir().unwrap();
+	let input = root.path().join("packs");
+	let output = root.path().join("merged");
+	let lang = "assets/minecraft/lang/en_us.json";
+	pack(
+		&input,
+		"a",
+		&[(lang, r#"{"block.minecraft.stone":"Stone"}"#)],
+	);
+	pack(
+		&input,
+		"b",
+		&[(lang, r#"{"block.minecraft.stone":"Rock"}"#)],
+	);
+
+	let result = ribozyme(&input, &output, &["--lang-policy", "fail"]);
+
+	assert!(!result.status.success());
+	assert!(!output.join(lang).exists());
+}
+
+#[test]
+fn converted_lang_follow_priority() {
+	let root = tempfile::tempdir().unwrap();
+	let input = root.path().join("packs");
+	pack(
+		&input,
+		"a",
+		&[("assets/minecraft/lang/en_us.json", r#"{"shared":"a"}"#)],
+	);
+	pack(
+		&input,
+		"b",
+		&[("assets/minecraft/lang/en_US.lang", "shared=b\n")],
+	);
+
+	let output = root.path().join("json");
+	let result = ribozyme(&input, &output, &["--convert-lang", "--pack-format", "15"]);
+	assert!(result.status.success());
+	let lang = fs::read_to_string(output.join("assets/minecraft/lang/en_us.json")).unwrap();
+	assert_eq!(lang, r#"{"shared":"b"}"#);
+
+	let output = root.path().join("legacy");
+	let result = ribozyme(&input, &output, &["--convert-lang", "--pack-format", "3"]);
+	assert!(result.status.success());
+	let lang = fs::read_to_string(output.join("assets/minecraft/lang/en_US.lang")).unwrap();
+	assert_eq!(lang, "shared=b\n");
+}